@@ -0,0 +1,146 @@
+// Copyright (C) 2018 Stephane Raux. Distributed under the MIT license.
+
+use crate::Retry;
+use crate::policy::{DefaultPolicy, RetryPolicy};
+use std::io::{ErrorKind, Read, self, Write};
+use std::process::{Child, ChildStdin, ChildStdout};
+
+/// Wrapper retrying reads from the standard output of a child process and
+/// writes to its standard input.
+///
+/// Pipes to child processes are a common source of `Interrupted` errors on
+/// Unix. See [`Retry::child_transport`].
+pub type ChildTransport<W = ChildStdin, R = ChildStdout, P = DefaultPolicy> =
+    Retry<ChildPipes<W, R>, P>;
+
+/// Standard input and output of a child process, written to and read from as
+/// a single value.
+///
+/// Once the standard input is closed with [`Retry::close_stdin`], writes fail
+/// with an error of kind `BrokenPipe` and flushes do nothing.
+#[derive(Debug)]
+pub struct ChildPipes<W = ChildStdin, R = ChildStdout> {
+    stdin: Option<W>,
+    stdout: R,
+}
+
+impl<W, R> ChildPipes<W, R> {
+    /// Combines the standard input and output of a child process.
+    pub fn new(stdin: W, stdout: R) -> Self {
+        ChildPipes {stdin: Some(stdin), stdout}
+    }
+}
+
+impl Retry<ChildPipes> {
+    /// Takes the piped standard input and output of a child process and wraps
+    /// them in a single transport.
+    ///
+    /// Returns `None`, leaving `child` unchanged, if its standard input or
+    /// output was not piped or was already taken.
+    pub fn child_transport(child: &mut Child) -> Option<Self> {
+        if child.stdin.is_none() || child.stdout.is_none() {
+            return None;
+        }
+        let pipes = ChildPipes::new(child.stdin.take()?, child.stdout.take()?);
+        Some(Retry::new(pipes))
+    }
+}
+
+impl<W: Write, R, P: RetryPolicy> Retry<ChildPipes<W, R>, P> {
+    /// Flushes the wrapper and closes the standard input of the child
+    /// process, which then sees the end of its input.
+    ///
+    /// Reading the output of the child remains possible. If flushing fails,
+    /// the standard input is left open and the error is returned. Closing an
+    /// already closed standard input does nothing.
+    pub fn close_stdin(&mut self) -> io::Result<()> {
+        if self.inner.stdin.is_some() {
+            self.flush()?;
+            self.inner.stdin = None;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the standard input of the child process was closed.
+    pub fn is_stdin_closed(&self) -> bool {
+        self.inner.stdin.is_none()
+    }
+}
+
+impl<W, R: Read> Read for ChildPipes<W, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdout.read(buf)
+    }
+}
+
+impl<W: Write, R> Write for ChildPipes<W, R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.stdin {
+            Some(ref mut stdin) => stdin.write(buf),
+            None => Err(io::Error::new(ErrorKind::BrokenPipe,
+                "standard input closed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.stdin {
+            Some(ref mut stdin) => stdin.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::Retry;
+    use partial_io::{PartialOp, PartialRead, PartialWrite};
+    use std::io::{ErrorKind, Read, Write};
+    use std::process::{Command, Stdio};
+    use super::{ChildPipes, ChildTransport};
+
+    fn cat() -> std::process::Child {
+        Command::new("cat")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap()
+    }
+
+    #[test]
+    fn round_trips_through_child_processes() {
+        let mut child = cat();
+        let ops = || vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let stdin = PartialWrite::new(child.stdin.take().unwrap(), ops());
+        let stdout = PartialRead::new(child.stdout.take().unwrap(), ops());
+        let mut transport: ChildTransport<_, _> =
+            Retry::new(ChildPipes::new(stdin, stdout));
+        transport.write_all(b"Echoed by cat").unwrap();
+        transport.close_stdin().unwrap();
+        assert!(transport.is_stdin_closed());
+        assert_eq!(transport.write(b"late").unwrap_err().kind(),
+            ErrorKind::BrokenPipe);
+        transport.flush().unwrap();
+        let mut out = String::new();
+        transport.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "Echoed by cat");
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn takes_pipes_of_child_processes() {
+        let mut child = cat();
+        let mut transport = Retry::child_transport(&mut child).unwrap();
+        assert!(Retry::child_transport(&mut child).is_none());
+        transport.write_all(b"ping").unwrap();
+        transport.close_stdin().unwrap();
+        transport.close_stdin().unwrap();
+        let mut out = Vec::new();
+        transport.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"ping");
+        assert!(child.wait().unwrap().success());
+    }
+}
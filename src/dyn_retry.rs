@@ -0,0 +1,105 @@
+// Copyright (C) 2018 Stephane Raux. Distributed under the MIT license.
+
+use crate::{Retry, WriteReport};
+use crate::policy::RetryPolicy;
+use std::io::{Read, self, Write};
+
+/// Object-safe access to the settings shared by all [`Retry`] wrappers.
+pub trait DynRetry {
+    /// See [`Retry::remaining_budget`].
+    fn remaining_budget(&self) -> Option<u64>;
+
+    /// See [`Retry::refill_budget`].
+    fn refill_budget(&mut self, n: u64);
+}
+
+/// Object-safe trait for retrying readers.
+///
+/// This allows readers of different types to be stored as
+/// `Box<dyn DynRetryRead>` while preserving access to the methods specific to
+/// [`Retry`].
+pub trait DynRetryRead: DynRetry + Read {
+    /// See [`Retry::skip`].
+    fn skip(&mut self, n: u64) -> io::Result<u64>;
+}
+
+/// Object-safe trait for retrying writers.
+///
+/// This allows writers of different types to be stored as
+/// `Box<dyn DynRetryWrite>` while preserving access to the methods specific to
+/// [`Retry`].
+pub trait DynRetryWrite: DynRetry + Write {
+    /// See [`Retry::write_all_reporting`].
+    fn write_all_reporting(&mut self, buf: &[u8]) -> io::Result<WriteReport>;
+}
+
+impl<T, P> DynRetry for Retry<T, P> {
+    fn remaining_budget(&self) -> Option<u64> {
+        Retry::remaining_budget(self)
+    }
+
+    fn refill_budget(&mut self, n: u64) {
+        Retry::refill_budget(self, n)
+    }
+}
+
+impl<T: Read, P: RetryPolicy> DynRetryRead for Retry<T, P> {
+    fn skip(&mut self, n: u64) -> io::Result<u64> {
+        Retry::skip(self, n)
+    }
+}
+
+impl<T: Write, P: RetryPolicy> DynRetryWrite for Retry<T, P> {
+    fn write_all_reporting(&mut self, buf: &[u8]) -> io::Result<WriteReport> {
+        Retry::write_all_reporting(self, buf)
+    }
+}
+
+impl<T, P> Retry<T, P>
+where
+    T: Read + 'static,
+    P: RetryPolicy + 'static,
+{
+    /// Erases the type of the wrapper, keeping access to its retrying reader
+    /// methods.
+    pub fn into_dyn_retry(self) -> Box<dyn DynRetryRead> {
+        Box::new(self)
+    }
+}
+
+impl<T, P> Retry<T, P>
+where
+    T: Write + 'static,
+    P: RetryPolicy + 'static,
+{
+    /// Erases the type of the wrapper, keeping access to its retrying writer
+    /// methods.
+    pub fn into_dyn_retry_write(self) -> Box<dyn DynRetryWrite> {
+        Box::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Retry, RetryProfile};
+    use partial_io::{PartialOp, PartialRead};
+    use std::io::{Cursor, ErrorKind, Read};
+    use super::DynRetryRead;
+
+    #[test]
+    fn reads_through_erased_readers() {
+        let ops = vec![PartialOp::Err(ErrorKind::Interrupted)];
+        let mut readers: Vec<Box<dyn DynRetryRead>> = vec![
+            Retry::new(PartialRead::new(&b"skip first"[..], ops))
+                .into_dyn_retry(),
+            Retry::with_profile(Cursor::new(b"skip second".to_vec()),
+                RetryProfile::Conservative).into_dyn_retry(),
+        ];
+        let mut out = Vec::new();
+        for reader in &mut readers {
+            assert_eq!(reader.skip(5).unwrap(), 5);
+            reader.read_to_end(&mut out).unwrap();
+        }
+        assert_eq!(out, b"firstsecond");
+    }
+}
@@ -2,142 +2,5635 @@
 
 //! This crate defines a wrapper around readers (buffered or not) and writers
 //! to retry on IO errors of kind `Interrupted`.
+//!
+//! Which errors are retried, how many times and how long to wait between
+//! attempts can be configured with a [`RetryBuilder`], one of the
+//! [`RetryProfile`] presets or a custom [`RetryPolicy`].
 
 #![deny(missing_docs)]
 #![deny(warnings)]
 
+pub mod policy;
+
+mod child;
+mod dyn_retry;
+mod recording;
+mod shared;
+#[cfg(unix)]
+mod sys;
+
+pub use child::{ChildPipes, ChildTransport};
+pub use dyn_retry::{DynRetry, DynRetryRead, DynRetryWrite};
+pub use recording::{Operation, Recording};
+pub use shared::{Shared, SharedRetry};
+
+use policy::{DefaultPolicy, DowncastPolicy, Or, RetryPolicy, RetryReason};
+use std::collections::VecDeque;
+use std::error::Error;
 use std::fmt;
-use std::io::{BufRead, ErrorKind, Read, Seek, self, Write};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, IoSliceMut, Read};
+use std::io::{Seek, SeekFrom, self, Write};
+use std::mem::{self, MaybeUninit};
+use std::net::{TcpStream, UdpSocket};
+use std::ops::{Deref, DerefMut};
+use std::process::{Child, ChildStdin, ChildStdout};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::sync::mpsc::{Sender, SyncSender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// Wrapper for readers, buffered readers and writers to automatically retry
 /// as long as an IO error of kind `Interrupted` occurs.
 ///
-/// All methods are forwarded to the wrapped type.
+/// Other kinds of errors can be retried as well and retries can be bounded by
+/// configuring the wrapper with a [`RetryBuilder`] or a custom
+/// [`RetryPolicy`]. Bulk methods like `read_exact` or `write_all` are
+/// implemented on top of the retrying `read`, `fill_buf` and `write` so that
+/// they honor the same settings. `flush` is retried as well, so flushing
+/// nested wrappers like `Retry<BufWriter<Retry<File>>>` retries at each layer
+/// down to the bottom.
+///
+/// With the default settings, errors of kind `WouldBlock` are returned
+/// immediately while `Interrupted` is retried. This is the recommended setting
+/// for non-blocking IO objects driven by an event loop, which can then wait
+/// for readiness itself. To block until they are ready instead, see
+/// [`RetryBuilder::waiter`].
+///
+/// A few settings depend on the type of the inner value, e.g. the factory
+/// building a new one to reconnect. A [`RetryBuilder`] does not know that type,
+/// so these are set on the wrapper itself instead:
+/// [`reconnect_on`](Retry::reconnect_on),
+/// [`rewind_and_retry_on`](Retry::rewind_and_retry_on),
+/// [`flush_on_replace`](Retry::flush_on_replace) and, on Unix,
+/// `poll_would_block`. They take and return the wrapper, to be chained right
+/// after building it. Clones of the wrapper keep them, but they are not part
+/// of its [`RetryConfig`], so each wrapper sharing settings through
+/// [`Retry::with_config`] must be given them.
+///
+/// `Retry<T, P>` is `Send` (resp. `Sync`) if and only if both `T` and `P` are.
+/// Hooks and factories given to the wrapper must be `Send` and thus never
+/// make it lose either property.
+///
+/// ```compile_fail
+/// fn assert_send<T: Send>(_: T) {}
+///
+/// // `Rc` is not `Send`, and neither is a wrapper around it.
+/// let reader = retrio::Retry::new(std::rc::Rc::new(&b"data"[..]));
+/// assert_send(reader);
+/// ```
 #[derive(Clone, Debug)]
-pub struct Retry<T> {
+pub struct Retry<T, P = DefaultPolicy> {
     inner: T,
+    config: Arc<RetryConfig<P>>,
+    budget: Option<u64>,
+    available: usize,
+    staged: VecDeque<u8>,
+    pending: Vec<u8>,
+    scratch: Vec<u8>,
+    dirty: bool,
+    unflushed: u64,
+    failing_since: Option<Instant>,
+    throughput: Throughput,
+    created: Instant,
+    progress: u64,
+    position: u64,
+    #[cfg(feature = "stats")]
+    stats: Stats,
+    enabled: bool,
+    events: RetryEvents,
+    last_error: Option<ErrorCopy>,
+    gave_up_at_limit: bool,
+    deferred_error: Option<ErrorCopy>,
+    deadline: Option<Instant>,
+    overrides: Option<RetryOverrides>,
+    reconnect: Option<(ErrorKind, ReconnectHook<T>)>,
+    waiter: Option<Waiter<T>>,
+    rewind: Option<Rewind<T>>,
+    read_start: Option<u64>,
+    flush_on_replace: Option<fn(&mut Self) -> io::Result<()>>,
+    write_before_seek: Option<fn(&mut Self) -> io::Result<()>>,
+    broken_pipe_reported: bool,
+    write_calls: u64,
+    write_retries: u64,
 }
 
 impl<T> Retry<T> {
     /// Wraps a value.
     pub fn new(inner: T) -> Self {
-        Retry {inner}
+        RetryBuilder::new().build(inner)
+    }
+
+    /// Wraps a value and passes the wrapper to `adapt`, returning the adapter
+    /// it builds on top of it.
+    ///
+    /// Transforming adapters such as decoders, decompressors or decryptors
+    /// often give up on the first error they get, leaving their state
+    /// inconsistent. Retries should thus happen as close to the source as
+    /// possible, below any such adapter, which this makes explicit.
+    ///
+    /// ```
+    /// use retrio::Retry;
+    /// use std::io::{BufReader, Cursor, Read};
+    ///
+    /// let source = Cursor::new(b"abc".to_vec());
+    /// let mut reader = Retry::under(source, BufReader::new);
+    /// let mut s = String::new();
+    /// reader.read_to_string(&mut s).unwrap();
+    /// assert_eq!(s, "abc");
+    /// ```
+    pub fn under<A, F>(inner: T, adapt: F) -> A
+    where
+        F: FnOnce(Self) -> A,
+    {
+        adapt(Retry::new(inner))
+    }
+
+    /// Wraps the value returned by `f`, or returns its error unchanged.
+    pub fn try_new<E, F>(f: F) -> Result<Self, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        f().map(Retry::new)
+    }
+
+    /// Wraps a value using the settings of a preset profile.
+    pub fn with_profile(inner: T, profile: RetryProfile) -> Self {
+        RetryBuilder::new().profile(profile).build(inner)
+    }
+
+    /// Wraps a value and calls `f` whenever an error is returned after giving
+    /// up on retrying it.
+    ///
+    /// See [`RetryBuilder::on_give_up`].
+    pub fn on_give_up<F>(inner: T, f: F) -> Self
+    where
+        F: FnMut(&io::Error, u32) + Send + 'static,
+    {
+        RetryBuilder::new().on_give_up(f).build(inner)
+    }
+
+    /// Wraps a value and calls `f` before each retry.
+    ///
+    /// See [`RetryBuilder::on_retry`].
+    pub fn on_retry<F>(inner: T, f: F) -> Self
+    where
+        F: FnMut(&RetryInfo) + Send + 'static,
+    {
+        RetryBuilder::new().on_retry(f).build(inner)
+    }
+
+    /// Wraps a value allowing `n` retries over its lifetime.
+    ///
+    /// See [`RetryBuilder::total_budget`].
+    pub fn with_total_budget(inner: T, n: u64) -> Self {
+        RetryBuilder::new().total_budget(n).build(inner)
+    }
+
+    /// Wraps a reader that fails with `TimedOut` once it reads less than
+    /// `bytes_per_sec` bytes per second on average over `window`.
+    ///
+    /// See [`RetryBuilder::min_throughput`].
+    pub fn with_min_throughput(inner: T, bytes_per_sec: u64, window: Duration)
+        -> Self
+    {
+        RetryBuilder::new().min_throughput(bytes_per_sec, window).build(inner)
+    }
+
+    /// Wraps a buffered reader and calls `f` with the number of bytes obtained
+    /// whenever `fill_buf` reads from its source.
+    ///
+    /// See [`RetryBuilder::on_refill`].
+    pub fn on_refill<F>(inner: T, f: F) -> Self
+    where
+        F: FnMut(usize) + Send + 'static,
+    {
+        RetryBuilder::new().on_refill(f).build(inner)
+    }
+
+    /// Wraps a writer and coalesces small writes until `threshold` bytes are
+    /// pending.
+    ///
+    /// See [`RetryBuilder::coalesce`].
+    pub fn coalescing(inner: T, threshold: usize) -> Self {
+        RetryBuilder::new().coalesce(threshold).build(inner)
+    }
+
+    /// Wraps a value and calls `waiter` to wait for it to be ready whenever an
+    /// operation fails with `WouldBlock`.
+    ///
+    /// See [`RetryBuilder::waiter`].
+    pub fn with_waiter<F>(inner: T, waiter: F) -> Self
+    where
+        F: FnMut(Interest) -> io::Result<()> + Send + 'static,
+    {
+        RetryBuilder::new().waiter(waiter).build(inner)
+    }
+
+    /// Wraps a value and calls `hook` to decide what to do when operations
+    /// have kept being retried for `quiet_period` without succeeding.
+    ///
+    /// See [`RetryBuilder::escalate_after`].
+    pub fn with_escalation<F>(inner: T, quiet_period: Duration, hook: F)
+        -> Self
+    where
+        F: FnMut() -> Escalate + Send + 'static,
+    {
+        RetryBuilder::new().escalate_after(quiet_period, hook).build(inner)
+    }
+}
+
+impl<T, P> Retry<T, P> {
+    /// Wraps a value with settings that may be shared with other wrappers.
+    ///
+    /// Sharing settings saves memory when many wrappers are configured the
+    /// same way. The hooks are then shared as well and see the operations of
+    /// all these wrappers.
+    ///
+    /// ```
+    /// use retrio::{Retry, RetryConfig};
+    /// use std::io::{Cursor, ErrorKind};
+    ///
+    /// let config = RetryConfig::builder()
+    ///     .retry_on(ErrorKind::TimedOut)
+    ///     .build_config();
+    /// let first = Retry::with_config(Cursor::new(vec![1]), config.clone());
+    /// let second = Retry::with_config(Cursor::new(vec![2]), config);
+    /// ```
+    pub fn with_config(inner: T, config: Arc<RetryConfig<P>>) -> Self {
+        let created = config.settings.now();
+        Retry {
+            inner,
+            budget: config.settings.total_budget,
+            waiter: config.settings.waiter.clone().map(Waiter::Custom),
+            config,
+            available: 0,
+            staged: VecDeque::new(),
+            pending: Vec::new(),
+            scratch: Vec::new(),
+            dirty: false,
+            unflushed: 0,
+            failing_since: None,
+            throughput: Throughput::default(),
+            created,
+            progress: 0,
+            position: 0,
+            #[cfg(feature = "stats")]
+            stats: Stats::default(),
+            enabled: true,
+            events: RetryEvents::default(),
+            last_error: None,
+            gave_up_at_limit: false,
+            deferred_error: None,
+            deadline: None,
+            overrides: None,
+            reconnect: None,
+            rewind: None,
+            read_start: None,
+            flush_on_replace: None,
+            write_before_seek: None,
+            broken_pipe_reported: false,
+            write_calls: 0,
+            write_retries: 0,
+        }
+    }
+
+    /// Returns the settings of the wrapper, which may be shared with other
+    /// wrappers.
+    pub fn config(&self) -> &Arc<RetryConfig<P>> {
+        &self.config
     }
 
     /// Returns the inner value.
+    ///
+    /// Bytes already moved out of the inner reader into the wrapper, e.g. by
+    /// [`fill_to_capacity`](Retry::fill_to_capacity) or
+    /// [`RetryBuilder::read_buffer`], are lost.
     pub fn into_inner(self) -> T {
         self.inner
     }
-}
 
-impl<T: Read> Read for Retry<T> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        loop {
-            match self.inner.read(buf) {
-                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
-                res => return res,
+    /// Gives direct access to the inner value until the returned guard is
+    /// dropped.
+    ///
+    /// Operations performed through the guard are not retried, nor tracked by
+    /// the wrapper, which is borrowed until the guard is dropped. Bytes staged
+    /// by the wrapper, e.g. by [`unread`](Retry::unread), are skipped.
+    ///
+    /// ```
+    /// use retrio::Retry;
+    /// use std::io::{Cursor, Read};
+    ///
+    /// let mut reader = Retry::new(Cursor::new(vec![1, 2]));
+    /// let mut byte = [0];
+    /// reader.raw_scope().read_exact(&mut byte).unwrap();
+    /// reader.read_exact(&mut byte).unwrap();
+    /// assert_eq!(byte, [2]);
+    /// ```
+    pub fn raw_scope(&mut self) -> RawGuard<'_, T> {
+        RawGuard(&mut self.inner)
+    }
+
+    /// Replaces the inner value with `new` and returns the previous one.
+    ///
+    /// The settings and counters of the wrapper are kept, which allows e.g.
+    /// rotating log files. Bytes moved out of the previous value into the
+    /// wrapper are lost like with [`into_inner`](Retry::into_inner). If
+    /// [`flush_on_replace`](Retry::flush_on_replace) is set, the wrapper is
+    /// flushed first, and otherwise bytes coalesced by the wrapper that were
+    /// not written yet go to `new`. Errors from that flush are ignored, as the
+    /// previous value is returned and can be flushed again to find out.
+    pub fn replace_inner(&mut self, new: T) -> T {
+        if let Some(flush) = self.flush_on_replace {
+            let _ = flush(self);
+        }
+        self.discard_buffered();
+        mem::replace(&mut self.inner, new)
+    }
+
+    /// Returns the retry policy.
+    pub fn policy(&self) -> &P {
+        &self.config.policy
+    }
+
+    /// Returns how many retries are left in the total budget, or `None` if the
+    /// wrapper has no total budget.
+    pub fn remaining_budget(&self) -> Option<u64> {
+        self.budget
+    }
+
+    /// Allows `n` more retries by adding them to the remaining total budget.
+    ///
+    /// This has no effect if the wrapper was built without a total budget.
+    pub fn refill_budget(&mut self, n: u64) {
+        if let Some(ref mut budget) = self.budget {
+            *budget = budget.saturating_add(n);
+        }
+    }
+
+    /// Returns the number of retries performed over the lifetime of the
+    /// wrapper.
+    ///
+    /// This requires the `stats` feature, which is enabled by default.
+    #[cfg(feature = "stats")]
+    pub fn retries(&self) -> u64 {
+        self.stats.retries
+    }
+
+    /// Returns the number of calls to `read`, `fill_buf` and `write` over the
+    /// lifetime of the wrapper, be they successful or not.
+    ///
+    /// Each call counts once regardless of how many times it was retried, so
+    /// that dividing [`retries`](Retry::retries) by this gives the average
+    /// number of retries per operation. Calls made to implement other methods,
+    /// e.g. `read_exact`, count as well.
+    ///
+    /// This requires the `stats` feature, which is enabled by default.
+    #[cfg(feature = "stats")]
+    pub fn operation_count(&self) -> u64 {
+        self.stats.operations
+    }
+
+    /// Returns the number of bytes read or consumed through the wrapper over
+    /// its lifetime.
+    ///
+    /// This requires the `stats` feature, which is enabled by default.
+    #[cfg(feature = "stats")]
+    pub fn bytes_read(&self) -> u64 {
+        self.stats.bytes_read
+    }
+
+    /// Returns the number of bytes written through the wrapper over its
+    /// lifetime.
+    ///
+    /// This requires the `stats` feature, which is enabled by default.
+    #[cfg(feature = "stats")]
+    pub fn bytes_written(&self) -> u64 {
+        self.stats.bytes_written
+    }
+
+    /// Returns the counters of the wrapper and resets them to zero.
+    ///
+    /// This allows reporting activity per interval, e.g. every few seconds.
+    /// The kind of the [last error](Retry::last_error) is included but the
+    /// error itself is kept.
+    ///
+    /// This requires the `stats` feature, which is enabled by default.
+    #[cfg(feature = "stats")]
+    pub fn take_stats(&mut self) -> RetryStats {
+        let stats = mem::take(&mut self.stats);
+        RetryStats {
+            operations: stats.operations,
+            retries: stats.retries,
+            bytes_read: stats.bytes_read,
+            bytes_written: stats.bytes_written,
+            last_error: self.last_error.as_ref().map(|e| e.0.kind()),
+        }
+    }
+
+    fn discard_buffered(&mut self) {
+        self.available = 0;
+        self.staged.clear();
+    }
+
+    fn count_operation(&mut self) {
+        #[cfg(feature = "stats")]
+        {
+            self.stats.operations = self.stats.operations.saturating_add(1);
+        }
+    }
+
+    fn advance_read(&mut self, n: usize) {
+        if self.config.settings.min_throughput.is_some() && n > 0 {
+            let now = self.config.settings.now();
+            self.throughput.start.get_or_insert(now);
+            self.throughput.samples.push_back((now, n as u64));
+            self.throughput.total += n as u64;
+        }
+        if let Some(ref events) = self.config.settings.events {
+            if n > 0 {
+                self.progress += n as u64;
+                let total = self.progress;
+                events.with(|f| f(RetryEvent::Progress {total}));
             }
         }
+        self.position += n as u64;
+        #[cfg(feature = "stats")]
+        {
+            self.stats.bytes_read += n as u64;
+        }
     }
 
-    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
-        self.inner.read_to_end(buf)
+    fn unread_bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes.iter().rev() {
+            self.staged.push_front(b);
+        }
+        self.position = self.position.saturating_sub(bytes.len() as u64);
+        self.progress = self.progress.saturating_sub(bytes.len() as u64);
+        #[cfg(feature = "stats")]
+        {
+            self.stats.bytes_read =
+                self.stats.bytes_read.saturating_sub(bytes.len() as u64);
+        }
     }
 
-    fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
-        self.inner.read_to_string(buf)
+    fn advance_written(&mut self, n: usize) {
+        self.dirty |= n > 0;
+        self.unflushed += n as u64;
+        self.position += n as u64;
+        #[cfg(feature = "stats")]
+        {
+            self.stats.bytes_written += n as u64;
+        }
     }
 
-    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
-        self.inner.read_exact(buf)
+    /// Returns a copy of the last error returned by the inner value during the
+    /// last operation, be it retried or returned, if any.
+    ///
+    /// The copy has the same raw OS error code if there is one, and otherwise
+    /// the same kind and message as the original error. It is cleared when
+    /// the next operation starts.
+    pub fn last_error(&self) -> Option<&io::Error> {
+        self.last_error.as_ref().map(|e| &e.0)
+    }
+
+    /// Returns how long a read is retried for, if bounded.
+    ///
+    /// See [`RetryBuilder::read_deadline`].
+    pub fn read_deadline(&self) -> Option<Duration> {
+        self.config.settings.read_deadline
+    }
+
+    /// Returns how long a write is retried for, if bounded.
+    ///
+    /// See [`RetryBuilder::write_deadline`].
+    pub fn write_deadline(&self) -> Option<Duration> {
+        self.config.settings.write_deadline
+    }
+
+    /// Sets whether errors are retried.
+    ///
+    /// While retrying is disabled, every error is returned immediately as if
+    /// the policy had given up on it. Retrying is enabled by default.
+    pub fn set_retry_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Returns whether errors are retried.
+    ///
+    /// See [`set_retry_enabled`](Retry::set_retry_enabled).
+    pub fn retry_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Replaces the inner value with a new one built by `factory` whenever an
+    /// operation fails with an error of the given kind, and retries the
+    /// operation with the new value.
+    ///
+    /// This is meant to resume a stream after its connection was reset, e.g.
+    /// with `ErrorKind::ConnectionReset`. Errors of this kind are handled
+    /// regardless of which errors the policy retries, but each reconnection
+    /// counts as a retry against the total budget and is reported to the
+    /// [`on_retry`](RetryBuilder::on_retry) hook. Reconnections stop once an
+    /// operation was retried as many times as allowed by
+    /// [`RetryPolicy::retry_limit`], e.g. with
+    /// [`max_retries`](RetryBuilder::max_retries), and the error is then left
+    /// to the policy. If `factory` fails, its error is returned.
+    ///
+    /// `factory` returns the type of the inner value, which is why this is a
+    /// setting of the wrapper, see [`Retry`].
+    pub fn reconnect_on<F>(mut self, kind: ErrorKind, factory: F) -> Self
+    where
+        F: FnMut() -> io::Result<T> + Send + 'static,
+    {
+        self.reconnect = Some((kind, Hook(Arc::new(Mutex::new(factory)))));
+        self
     }
 }
 
-impl<T: BufRead> BufRead for Retry<T> {
-    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+impl<T: Read + Seek, P> Retry<T, P> {
+    /// Seeks back to where a read started and retries it whenever it fails
+    /// with an error of the given kind.
+    ///
+    /// This is meant for readers backed by a resource that can be read again,
+    /// where an error such as `InvalidData` may go away when re-reading. The
+    /// position is queried before each read, which costs a call to
+    /// `stream_position`. Errors of this kind are handled regardless of which
+    /// errors the policy retries, but each rewind counts as a retry against
+    /// the total budget and is reported to the
+    /// [`on_retry`](RetryBuilder::on_retry) hook. Rewinds stop once a read was
+    /// retried as many times as allowed by [`RetryPolicy::retry_limit`], e.g.
+    /// with [`max_retries`](RetryBuilder::max_retries), and the error is then
+    /// left to the policy. If seeking fails, its error is returned.
+    ///
+    /// Rewinding requires the inner value to implement `Seek`, which is why
+    /// this is a setting of the wrapper, see [`Retry`].
+    pub fn rewind_and_retry_on(mut self, kind: ErrorKind) -> Self {
+        self.rewind = Some(Rewind {
+            kind,
+            position: T::stream_position,
+            seek: |inner, pos| inner.seek(SeekFrom::Start(pos)),
+        });
+        self
+    }
+}
+
+impl Retry<ChildStdin> {
+    /// Takes the piped standard input of a child process and wraps it.
+    ///
+    /// Returns `None` if the standard input of the child was not piped or was
+    /// already taken.
+    pub fn child_stdin(child: &mut Child) -> Option<Self> {
+        child.stdin.take().map(Retry::new)
+    }
+}
+
+impl Retry<ChildStdout> {
+    /// Takes the piped standard output of a child process and wraps it.
+    ///
+    /// Returns `None` if the standard output of the child was not piped or was
+    /// already taken.
+    pub fn child_stdout(child: &mut Child) -> Option<Self> {
+        child.stdout.take().map(Retry::new)
+    }
+}
+
+#[cfg(unix)]
+impl<T: AsRawFd> Retry<T> {
+    /// Wraps a socket with read and write deadlines matching its
+    /// `SO_RCVTIMEO` and `SO_SNDTIMEO` options, if set.
+    ///
+    /// Reads and writes then stop being retried once the timeouts of the
+    /// socket have elapsed. See [`RetryBuilder::read_deadline`] and
+    /// [`RetryBuilder::write_deadline`]. Errors from querying the options are
+    /// returned.
+    pub fn from_socket(inner: T) -> io::Result<Self> {
+        let (read_timeout, write_timeout) = sys::socket_timeouts(&inner)?;
+        let mut builder = RetryBuilder::new();
+        if let Some(timeout) = read_timeout {
+            builder = builder.read_deadline(timeout);
+        }
+        if let Some(timeout) = write_timeout {
+            builder = builder.write_deadline(timeout);
+        }
+        Ok(builder.build(inner))
+    }
+}
+
+#[cfg(unix)]
+impl<T: AsRawFd, P> Retry<T, P> {
+    /// Sets whether to wait for the file descriptor of the inner value to be
+    /// ready with `poll` when an operation fails with `WouldBlock`, and then
+    /// retry it.
+    ///
+    /// This makes a wrapped non-blocking file descriptor behave as a blocking
+    /// one, e.g. if `O_NONBLOCK` was unexpectedly set on it. Errors of kind
+    /// `WouldBlock` from reading and writing are then handled regardless of
+    /// the policy, but each wait counts as a retry against the total budget
+    /// and is reported to the [`on_retry`](RetryBuilder::on_retry) hook.
+    /// Waiting is bounded by the deadline of the operation, e.g. the
+    /// [read deadline](RetryBuilder::read_deadline), after which an error of
+    /// kind `TimedOut` is returned. Errors from `poll` itself are returned.
+    ///
+    /// Polling requires the inner value to implement `AsRawFd`, which is why
+    /// this is a setting of the wrapper, see [`Retry`].
+    pub fn poll_would_block(mut self, enabled: bool) -> Self {
+        self.waiter = if enabled {
+            Some(Waiter::Poll(sys::wait::<T>))
+        } else {
+            None
+        };
+        self
+    }
+}
+
+impl<T, P: RetryPolicy> Retry<T, P> {
+    /// Calls `op` with the inner value and retries it like any other operation
+    /// of the wrapper, with the same policy, budget and hooks.
+    ///
+    /// This gives IO methods specific to the inner value the same treatment
+    /// as reading and writing. Since the wrapper cannot tell what kind of
+    /// operation `op` performs, the read and write deadlines do not apply and
+    /// `WouldBlock` is left to the policy. Bytes transferred by `op` are not
+    /// counted in the position and statistics of the wrapper.
+    ///
+    /// ```
+    /// use retrio::Retry;
+    /// use std::net::UdpSocket;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut socket = Retry::new(UdpSocket::bind("127.0.0.1:0")?);
+    /// let addr = socket.retrying(|socket| socket.local_addr())?;
+    /// socket.retrying(|socket| socket.send_to(b"ping", addr))?;
+    /// let mut buf = [0u8; 4];
+    /// let (n, _) = socket.retrying(|socket| socket.recv_from(&mut buf))?;
+    /// assert_eq!(&buf[..n], b"ping");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn retrying<R, F>(&mut self, op: F) -> io::Result<R>
+    where
+        F: FnMut(&mut T) -> io::Result<R>,
+    {
+        self.retry(op)
+    }
+
+    fn retry<R, F>(&mut self, op: F) -> io::Result<R>
+    where
+        F: FnMut(&mut T) -> io::Result<R>,
+    {
+        self.retry_for(None, op)
+    }
+
+    fn retry_read<R, F>(&mut self, op: F) -> io::Result<R>
+    where
+        F: FnMut(&mut T) -> io::Result<R>,
+    {
+        if let Some(e) = self.deferred_error.take() {
+            return Err(e.0);
+        }
+        if let Some(position) = self.rewind.as_ref().map(|r| r.position) {
+            self.read_start = Some(self.retry(position)?);
+        }
+        let res = self.retry_for(Some(Interest::Read), op);
+        self.read_start = None;
+        res
+    }
+
+    fn retry_write<R, F>(&mut self, op: F) -> io::Result<R>
+    where
+        F: FnMut(&mut T) -> io::Result<R>,
+    {
+        self.retry_for(Some(Interest::Write), op)
+    }
+
+    fn retry_for<R, F>(&mut self, interest: Option<Interest>, op: F)
+        -> io::Result<R>
+    where
+        F: FnMut(&mut T) -> io::Result<R>,
+    {
+        let timeout = match interest {
+            Some(Interest::Read) => self.config.settings.read_deadline,
+            Some(Interest::Write) => self.config.settings.write_deadline,
+            None => None,
+        };
+        let previous = self.deadline;
+        if let Some(deadline) =
+            timeout.and_then(|timeout| {
+                self.config.settings.now().checked_add(timeout)
+            })
+        {
+            self.deadline =
+                Some(previous.map_or(deadline, |d| d.min(deadline)));
+        }
+        let res = self.retry_loop(interest, op);
+        self.deadline = previous;
+        res
+    }
+
+    fn retry_loop<R, F>(&mut self, interest: Option<Interest>, mut op: F)
+        -> io::Result<R>
+    where
+        F: FnMut(&mut T) -> io::Result<R>,
+    {
+        let mut retries = 0;
+        let mut slept = Duration::from_secs(0);
+        self.last_error = None;
+        self.gave_up_at_limit = false;
         loop {
-            match self.inner.fill_buf() {
-                Ok(_) => break,
-                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
-                Err(e) => return Err(e),
+            if interest == Some(Interest::Read) {
+                if let Some(e) = self.throughput_error() {
+                    self.last_error = Some(ErrorCopy::new(&e));
+                    return Err(self.give_up(e, retries));
+                }
             }
+            let e = match op(&mut self.inner) {
+                Ok(x) => {
+                    self.failing_since = None;
+                    if retries > 0 {
+                        self.flush_retry_events();
+                        self.recovered(retries);
+                    }
+                    return Ok(x);
+                }
+                Err(e) => e,
+            };
+            self.last_error = Some(ErrorCopy::new(&e));
+            let within_limits = self.within_limits(retries);
+            let reason = match self.retry_reason(&e, interest, retries) {
+                Some(reason) if self.enabled && within_limits => reason,
+                reason => {
+                    // The error would have been retried if not for a limit,
+                    // e.g. the retry limit of the policy.
+                    self.gave_up_at_limit = self.enabled && (reason.is_some()
+                        || self.retry_reason(&e, interest, 0).is_some());
+                    return Err(self.give_up_for(interest, e, retries + 1));
+                }
+            };
+            let reason = match self.escalate(reason) {
+                Some(reason) => reason,
+                None => {
+                    return Err(self.give_up_for(interest, e, retries + 1));
+                }
+            };
+            let delay = self.retry_delay(&e, reason, retries);
+            slept += delay;
+            if self.config.settings.max_backoff_total
+                .is_some_and(|max| slept > max)
+            {
+                self.gave_up_at_limit = true;
+                return Err(self.give_up_for(interest, e, retries + 1));
+            }
+            self.before_retry(&e, reason, retries, delay);
+            if let Err(e) = self.recover(reason, interest) {
+                return Err(self.give_up_for(interest, e, retries + 1));
+            }
+            retries += 1;
         }
-        self.inner.fill_buf()
     }
 
-    fn consume(&mut self, n: usize) {
-        self.inner.consume(n)
+    fn start_read_deadline(&mut self) {
+        let deadline = self.config.settings.read_deadline.and_then(|timeout| {
+            self.config.settings.now().checked_add(timeout)
+        });
+        if let Some(deadline) = deadline {
+            self.deadline =
+                Some(self.deadline.map_or(deadline, |d| d.min(deadline)));
+        }
     }
 
-    fn read_until(&mut self, byte: u8, buf: &mut Vec<u8>) -> io::Result<usize> {
-        self.inner.read_until(byte, buf)
+    // `Option::is_none_or` would require Rust 1.82.
+    #[allow(clippy::unnecessary_map_or)]
+    fn within_limits(&self, retries: u32) -> bool {
+        self.budget != Some(0) && !self.past_deadline()
+            && self.overrides.as_ref()
+                .and_then(|overrides| overrides.max_attempts)
+                .map_or(true, |max| retries + 1 < max)
     }
 
-    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
-        self.inner.read_line(buf)
+    // Decides whether to retry a read that succeeded without returning any
+    // byte, e.g. at the end of the stream, with the limits of `retry_loop`,
+    // and waits before the retry if so.
+    fn retry_empty_read(&mut self, e: &io::Error, reason: RetryReason,
+        retries: u32, slept: &mut Duration) -> io::Result<bool>
+    {
+        if !self.enabled || !self.within_limits(retries) {
+            return Ok(false);
+        }
+        let reason = match self.escalate(reason) {
+            Some(reason) => reason,
+            None => return Ok(false),
+        };
+        let delay = self.retry_delay(e, reason, retries);
+        *slept += delay;
+        if self.config.settings.max_backoff_total
+            .is_some_and(|max| *slept > max)
+        {
+            return Ok(false);
+        }
+        self.before_retry(e, reason, retries, delay);
+        self.recover(reason, Some(Interest::Read))?;
+        Ok(true)
+    }
+
+    // `Option::is_none_or` would require Rust 1.82.
+    #[allow(clippy::unnecessary_map_or)]
+    fn retry_reason(&self, e: &io::Error, interest: Option<Interest>,
+        retries: u32) -> Option<RetryReason>
+    {
+        let within_limit = self.config.policy.retry_limit()
+            .map_or(true, |max| retries < max);
+        match self.reconnect {
+            Some((kind, _)) if kind == e.kind() && within_limit =>
+                return Some(RetryReason::Reconnect),
+            _ => {}
+        }
+        match self.rewind {
+            Some(ref rewind) if rewind.kind == e.kind()
+                && self.read_start.is_some() && within_limit =>
+                return Some(RetryReason::Rewind),
+            _ => {}
+        }
+        if e.kind() == ErrorKind::WouldBlock && interest.is_some()
+            && self.waiter.is_some()
+        {
+            return Some(RetryReason::WouldBlock);
+        }
+        let kinds = self.overrides.as_ref()
+            .and_then(|overrides| overrides.kinds.as_ref());
+        let in_grace = within_limit
+            && self.config.settings.startup_grace.is_some_and(|grace| {
+                self.config.settings.now().duration_since(self.created) < grace
+            });
+        if let Some(kinds) = kinds {
+            if kinds.contains(&e.kind()) {
+                Some(match e.kind() {
+                    ErrorKind::Interrupted => RetryReason::Interrupted,
+                    _ => RetryReason::Kind,
+                })
+            } else if in_grace {
+                Some(RetryReason::StartupGrace)
+            } else {
+                None
+            }
+        } else if self.config.policy.should_retry(e, retries) {
+            Some(self.config.policy.retry_reason(e, retries))
+        } else if in_grace {
+            Some(RetryReason::StartupGrace)
+        } else {
+            None
+        }
+    }
+
+    fn throughput_error(&mut self) -> Option<io::Error> {
+        let (bytes_per_sec, window) = self.config.settings.min_throughput?;
+        let now = self.config.settings.now();
+        let throughput = &mut self.throughput;
+        let start = *throughput.start.get_or_insert(now);
+        while let Some(&(at, n)) = throughput.samples.front() {
+            if now.duration_since(at) < window {
+                break;
+            }
+            throughput.samples.pop_front();
+            throughput.total -= n;
+        }
+        let expected = bytes_per_sec as f64 * window.as_secs_f64();
+        if now.duration_since(start) >= window
+            && (throughput.total as f64) < expected
+        {
+            Some(io::Error::new(ErrorKind::TimedOut,
+                "throughput below minimum"))
+        } else {
+            None
+        }
+    }
+
+    fn escalate(&mut self, reason: RetryReason) -> Option<RetryReason> {
+        let (quiet_period, hook) = match self.config.settings.escalation {
+            Some((quiet_period, ref hook)) => (quiet_period, hook),
+            None => return Some(reason),
+        };
+        let now = self.config.settings.now();
+        let since = *self.failing_since.get_or_insert(now);
+        if now.duration_since(since) < quiet_period {
+            return Some(reason);
+        }
+        self.failing_since = Some(now);
+        match hook.with(|f| f()) {
+            Escalate::Continue => Some(reason),
+            Escalate::Fail => None,
+            Escalate::Reconnect if self.reconnect.is_some() =>
+                Some(RetryReason::Reconnect),
+            Escalate::Reconnect => None,
+        }
+    }
+
+    fn recover(&mut self, reason: RetryReason, interest: Option<Interest>)
+        -> io::Result<()>
+    {
+        match (reason, interest) {
+            (RetryReason::Reconnect, _) => {
+                if let Some((_, ref factory)) = self.reconnect {
+                    self.inner = factory.with(|f| f())?;
+                    self.available = 0;
+                }
+            }
+            (RetryReason::WouldBlock, Some(interest)) => {
+                if let Some(ref waiter) = self.waiter {
+                    let now = self.config.settings.now();
+                    let timeout = self.deadline.map(|deadline| {
+                        deadline.saturating_duration_since(now)
+                    });
+                    waiter.wait(&self.inner, interest, timeout)?;
+                }
+            }
+            (RetryReason::Rewind, _) => {
+                if let (Some(ref rewind), Some(start)) =
+                    (&self.rewind, self.read_start)
+                {
+                    (rewind.seek)(&mut self.inner, start)?;
+                    self.available = 0;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn past_deadline(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| self.config.settings.now() >= deadline)
+    }
+
+    // `Option::is_none_or` and `u32::is_multiple_of` would require Rust 1.82
+    // and 1.87.
+    #[allow(clippy::manual_is_multiple_of, clippy::unnecessary_map_or)]
+    fn report_retry(&mut self, e: &io::Error, reason: RetryReason,
+        retries: u32)
+    {
+        let hook = match self.config.settings.on_retry {
+            Some(ref hook) => hook,
+            None => return,
+        };
+        let now = self.config.settings.now();
+        let events = &mut self.events;
+        let event = (e.kind(), reason);
+        if events.last == Some(event) {
+            events.streak += 1;
+        } else {
+            events.last = Some(event);
+            events.streak = 1;
+        }
+        let report = events.streak == 1
+            || match self.config.settings.retry_event_coalescing {
+                None => true,
+                Some(RetryEventCoalescing::Count(n)) =>
+                    (events.streak - 1) % n.max(1) == 0,
+                Some(RetryEventCoalescing::Interval(interval)) =>
+                    events.last_report
+                        .map_or(true, |t| now.duration_since(t) >= interval),
+            };
+        if report {
+            let suppressed = mem::take(&mut events.suppressed);
+            events.pending = None;
+            events.last_report = Some(now);
+            hook.with(|f| {
+                f(&RetryInfo {error: e, reason, retries, suppressed})
+            });
+        } else {
+            events.suppressed += 1;
+            events.pending = Some(retries);
+        }
+    }
+
+    fn flush_retry_events(&mut self) {
+        let events = &mut self.events;
+        if let (Some(hook), Some((kind, reason)), Some(retries)) =
+            (&self.config.settings.on_retry, events.last, events.pending.take())
+        {
+            let suppressed =
+                mem::take(&mut events.suppressed).saturating_sub(1);
+            let error = io::Error::from(kind);
+            hook.with(|f| {
+                f(&RetryInfo {error: &error, reason, retries, suppressed})
+            });
+        }
+        events.last = None;
+    }
+
+    fn recovered(&self, retries: u32) {
+        if let Some(ref hook) = self.config.settings.on_recovered {
+            hook.with(|f| f(retries));
+        }
+    }
+
+    fn ignores_broken_pipe(&self, e: &io::Error) -> bool {
+        e.kind() == ErrorKind::BrokenPipe
+            && self.config.settings.ignore_broken_pipe
+    }
+
+    fn give_up_for(&mut self, interest: Option<Interest>, e: io::Error,
+        attempts: u32) -> io::Error
+    {
+        // An ignored broken pipe is not given up on, and its kind must not be
+        // changed by `map_error` for `write` to recognize it.
+        if interest == Some(Interest::Write) && self.ignores_broken_pipe(&e) {
+            return e;
+        }
+        self.give_up(e, attempts)
+    }
+
+    fn give_up(&mut self, e: io::Error, attempts: u32) -> io::Error {
+        self.flush_retry_events();
+        if let Some(ref hook) = self.config.settings.on_give_up {
+            hook.with(|f| f(&e, attempts));
+        }
+        if let Some(ref events) = self.config.settings.events {
+            events.with(|f| f(RetryEvent::GaveUp {kind: e.kind()}));
+        }
+        match self.config.settings.map_error {
+            Some(map) => map(e),
+            None => e,
+        }
+    }
+
+    fn retry_delay(&self, e: &io::Error, reason: RetryReason, retries: u32)
+        -> Duration
+    {
+        let delay = match reason {
+            RetryReason::WouldBlock => Duration::from_secs(0),
+            RetryReason::Eof => self.config.settings.retry_eof
+                .map_or(Duration::from_secs(0), |(_, backoff)| {
+                    backoff.delay(retries)
+                }),
+            _ => self.config.policy.backoff(e, retries),
+        };
+        match self.deadline {
+            Some(deadline) => delay.min(deadline
+                .saturating_duration_since(self.config.settings.now())),
+            None => delay,
+        }
+    }
+
+    // `u32::is_multiple_of` would require Rust 1.87.
+    #[allow(clippy::manual_is_multiple_of)]
+    fn before_retry(&mut self, e: &io::Error, reason: RetryReason,
+        retries: u32, delay: Duration)
+    {
+        if let Some(ref mut budget) = self.budget {
+            *budget -= 1;
+        }
+        #[cfg(feature = "stats")]
+        {
+            self.stats.retries += 1;
+        }
+        self.report_retry(e, reason, retries);
+        if let Some(ref events) = self.config.settings.events {
+            let attempt = retries + 1;
+            events.with(|f| f(RetryEvent::Retried {kind: e.kind(), attempt}));
+        }
+        if delay > Duration::from_secs(0) {
+            self.config.settings.sleep(delay);
+        } else if let Some(k) = self.config.settings.yield_every {
+            if (retries + 1) % k.max(1) == 0 {
+                thread::yield_now();
+            }
+        }
     }
 }
 
-impl<T: Write> Write for Retry<T> {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        loop {
-            match self.inner.write(buf) {
-                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
-                res => return res,
+/// Details about a retry, passed to the hook set with
+/// [`RetryBuilder::on_retry`].
+#[derive(Debug)]
+pub struct RetryInfo<'a> {
+    error: &'a io::Error,
+    reason: RetryReason,
+    retries: u32,
+    suppressed: u32,
+}
+
+impl RetryInfo<'_> {
+    /// Returns the error being retried.
+    ///
+    /// For retries of type [`RetryReason::EmptyFill`] or [`RetryReason::Eof`],
+    /// this is an error of kind `UnexpectedEof` made up for the occasion.
+    pub fn error(&self) -> &io::Error {
+        self.error
+    }
+
+    /// Returns why the operation is retried.
+    pub fn reason(&self) -> RetryReason {
+        self.reason
+    }
+
+    /// Returns the number of previous retries of the same operation.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Returns how many retries were not reported to the hook since its
+    /// previous call, because of
+    /// [`RetryBuilder::coalesce_retry_events`].
+    pub fn suppressed(&self) -> u32 {
+        self.suppressed
+    }
+}
+
+impl<T: Read, P: RetryPolicy> Retry<T, P> {
+    /// Reads and discards up to `n` bytes, retrying like `read`.
+    ///
+    /// Returns the number of bytes skipped, which is less than `n` only if the
+    /// end of the stream was reached. If the inner reader is buffered, its
+    /// buffered bytes are skipped before reading more from its source.
+    pub fn skip(&mut self, n: u64) -> io::Result<u64> {
+        let mut chunk = [0u8; CHUNK_SIZE];
+        let mut skipped = 0;
+        while skipped < n {
+            let len = (n - skipped).min(CHUNK_SIZE as u64) as usize;
+            match self.read(&mut chunk[..len])? {
+                0 => break,
+                k => skipped += k as u64,
             }
         }
+        Ok(skipped)
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        self.inner.flush()
+    /// Reads until the end of the stream or until `max_additional` bytes were
+    /// read, retrying like `read`, and appends the bytes to `buf`.
+    ///
+    /// Returns the number of bytes appended. The existing contents of `buf`
+    /// are kept, and so are the bytes appended before an error is returned,
+    /// as with `read_to_end`. This allows reusing the same buffer over many
+    /// streams while bounding how much it grows.
+    pub fn read_remaining_into(&mut self, buf: &mut Vec<u8>,
+        max_additional: usize) -> io::Result<usize>
+    {
+        let start = buf.len();
+        let mut chunk = [0u8; CHUNK_SIZE];
+        while buf.len() - start < max_additional {
+            let len = (max_additional - (buf.len() - start)).min(CHUNK_SIZE);
+            match self.read(&mut chunk[..len])? {
+                0 => break,
+                n => buf.extend_from_slice(&chunk[..n]),
+            }
+        }
+        Ok(buf.len() - start)
+    }
+
+    /// Checks that the end of the stream was reached, retrying like `read`.
+    ///
+    /// Returns an error of kind `InvalidData` if more bytes are available. The
+    /// byte read to find out is kept by the wrapper and returned by the next
+    /// read, so nothing is consumed either way.
+    pub fn expect_eof(&mut self) -> io::Result<()> {
+        let mut byte = [0u8];
+        match self.read(&mut byte)? {
+            0 => Ok(()),
+            _ => {
+                self.unread_bytes(&byte);
+                Err(io::Error::new(ErrorKind::InvalidData,
+                    "unexpected trailing data"))
+            }
+        }
+    }
+
+    /// Reads a frame made of a 32-bit big-endian length followed by that many
+    /// bytes, retrying like `read`, and returns the bytes.
+    ///
+    /// Returns an error of kind `InvalidData` if the length exceeds `max_len`,
+    /// in which case no byte of the payload is read, and of kind
+    /// `UnexpectedEof` if the stream ends in the middle of the frame.
+    pub fn read_frame(&mut self, max_len: usize) -> io::Result<Vec<u8>> {
+        let mut len = [0u8; 4];
+        self.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len > max_len {
+            return Err(io::Error::new(ErrorKind::InvalidData,
+                "frame longer than allowed"));
+        }
+        self.read_exact_vec(len)
+    }
+
+    /// Reads exactly `n` bytes into a new vector, retrying like `read_exact`,
+    /// and returns it.
+    ///
+    /// Returns an error of kind `UnexpectedEof` if the stream ends before `n`
+    /// bytes are read. The bytes read before an error are then lost, like
+    /// with `read_exact`.
+    pub fn read_exact_vec(&mut self, n: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; n];
+        self.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Reads into `buf` until it is full, the end of the stream is reached or
+    /// `budget` has elapsed, retrying like `read`, and returns the number of
+    /// bytes read.
+    ///
+    /// Unlike `read_exact`, running out of time is not an error: once `budget`
+    /// has elapsed, errors are no longer retried and the bytes read so far are
+    /// returned, even if `buf` is not full. The same goes for an error given
+    /// up on because of a retry limit, e.g. the one of the policy. The inner
+    /// reader is always called at least once, and an error is returned if it
+    /// failed before any byte was read. Any other error after that also ends
+    /// the read with the bytes read so far, so that they are not lost, and is
+    /// returned by the next read reaching the inner reader. An inner `read`
+    /// that blocks is not interrupted, so this may take longer than `budget`.
+    pub fn read_within(&mut self, buf: &mut [u8], budget: Duration)
+        -> io::Result<usize>
+    {
+        let previous = self.deadline;
+        if let Some(deadline) = self.config.settings.now().checked_add(budget) {
+            self.deadline =
+                Some(previous.map_or(deadline, |d| d.min(deadline)));
+        }
+        let mut filled = 0;
+        let res = loop {
+            if filled == buf.len() {
+                break Ok(filled);
+            }
+            match self.read(&mut buf[filled..]) {
+                Ok(0) => break Ok(filled),
+                Ok(n) => filled += n,
+                Err(_) if filled > 0
+                    && (self.gave_up_at_limit || self.past_deadline()) =>
+                    break Ok(filled),
+                Err(e) if filled > 0 => {
+                    self.deferred_error = Some(ErrorCopy(e));
+                    break Ok(filled);
+                }
+                Err(e) => break Err(e),
+            }
+            if self.past_deadline() {
+                break Ok(filled);
+            }
+        };
+        self.deadline = previous;
+        res
+    }
+
+    /// Reads into `buf` like `read`, with settings of `overrides` replacing
+    /// the ones of the wrapper for this call only.
+    ///
+    /// This is meant for the odd read needing different retries, e.g. a
+    /// health check that should fail fast, without building another wrapper.
+    /// Settings left to `None` in `overrides` are the ones of the wrapper.
+    ///
+    /// ```
+    /// use retrio::{Retry, RetryOverrides};
+    /// use std::io::Read;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut reader = Retry::new(&b"ok"[..]);
+    /// let mut buf = [0u8; 2];
+    /// let overrides = RetryOverrides {
+    ///     max_attempts: Some(1),
+    ///     ..RetryOverrides::default()
+    /// };
+    /// assert_eq!(reader.read_with(&mut buf, overrides)?, 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_with(&mut self, buf: &mut [u8], overrides: RetryOverrides)
+        -> io::Result<usize>
+    {
+        let previous_deadline = self.deadline;
+        if let Some(deadline) = overrides.deadline.and_then(|timeout| {
+            self.config.settings.now().checked_add(timeout)
+        }) {
+            self.deadline =
+                Some(previous_deadline.map_or(deadline, |d| d.min(deadline)));
+        }
+        let previous = self.overrides.replace(overrides);
+        let res = self.read(buf);
+        self.overrides = previous;
+        self.deadline = previous_deadline;
+        res
     }
 
-    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
-        self.inner.write_all(buf)
+    /// Reads into a buffer that may not be initialized, retrying like `read`.
+    ///
+    /// Returns the number of bytes read, `n`, which is at most `buf.len()`.
+    /// The first `n` elements of `buf` then hold the bytes read and callers
+    /// may rely on it in their own unsafe code, e.g. to call `assume_init` on
+    /// them. The other elements are left untouched, so they are initialized
+    /// only if they were before. On error, `buf` is left untouched.
+    ///
+    /// Uninitialized memory is never handed to the inner reader, as `read`
+    /// implementations are allowed to read from the buffer they are given.
+    /// The wrapper reads into a buffer of its own instead and copies the bytes
+    /// read to `buf`. That buffer is kept for later calls and grows to the
+    /// largest size requested, only its new part being zeroed, so memory is
+    /// zeroed once rather than on each call.
+    pub fn read_uninit(&mut self, buf: &mut [MaybeUninit<u8>])
+        -> io::Result<usize>
+    {
+        let len = buf.len();
+        let mut scratch = self.take_scratch(len);
+        let res = self.read(&mut scratch[..len]);
+        if let Ok(n) = res {
+            copy_init(buf, &scratch[..n.min(len)]);
+        }
+        self.scratch = scratch;
+        Ok(res?.min(len))
+    }
+
+    // Takes the scratch buffer used to read into uninitialized memory, making
+    // sure it holds at least `len` bytes.
+    fn take_scratch(&mut self, len: usize) -> Vec<u8> {
+        let mut scratch = mem::take(&mut self.scratch);
+        if scratch.len() < len {
+            scratch.resize(len, 0);
+        }
+        scratch
     }
 
-    fn write_fmt(&mut self, args: fmt::Arguments) -> io::Result<()> {
-        self.inner.write_fmt(args)
+    /// Reads into a sequence of buffers that may not be initialized, retrying
+    /// like `read`, and filling them in order.
+    ///
+    /// Returns the number of bytes read, `n`. The first `n` elements of the
+    /// concatenation of `bufs` then hold the bytes read and the other elements
+    /// are left untouched. This is the vectored counterpart of
+    /// [`read_uninit`](Retry::read_uninit): the buffer of the wrapper is split
+    /// like `bufs` and handed to the `read_vectored` method of the inner
+    /// reader at once. Reading into uninitialized memory through `BorrowedBuf`
+    /// requires an unstable compiler, which this stands in for.
+    pub fn read_vectored_uninit(&mut self,
+        bufs: &mut [&mut [MaybeUninit<u8>]]) -> io::Result<usize>
+    {
+        let total = bufs.iter().map(|buf| buf.len()).sum::<usize>();
+        let mut scratch = self.take_scratch(total);
+        let mut rest = &mut scratch[..total];
+        let mut slices = Vec::with_capacity(bufs.len());
+        for buf in bufs.iter() {
+            let (slice, tail) = mem::take(&mut rest).split_at_mut(buf.len());
+            slices.push(IoSliceMut::new(slice));
+            rest = tail;
+        }
+        let res = self.read_vectored(&mut slices);
+        if let Ok(n) = res {
+            let mut read = &scratch[..n.min(total)];
+            for buf in bufs.iter_mut() {
+                let len = read.len().min(buf.len());
+                copy_init(buf, &read[..len]);
+                read = &read[len..];
+            }
+        }
+        self.scratch = scratch;
+        Ok(res?.min(total))
     }
 }
 
-impl<T: Seek> Seek for Retry<T> {
-    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
-        self.inner.seek(pos)
+impl<T: BufRead, P> Retry<T, P> {
+    /// Returns how many of the bytes returned by the last call to `fill_buf`
+    /// have not been consumed yet, without performing any IO.
+    ///
+    /// This is kept up to date by `fill_buf`, `consume` and the reading and
+    /// seeking methods of the wrapper. Accessing the inner reader directly
+    /// makes it stale.
+    pub fn available(&self) -> usize {
+        self.available
+    }
+
+    /// Pushes `bytes` back so that they are returned by the next reads, before
+    /// any byte from the inner reader.
+    ///
+    /// Bytes unread by successive calls are returned in the reverse order of
+    /// the calls, as if each call put its bytes back in front of the stream.
+    /// They are returned by `fill_buf` on their own, without bytes from the
+    /// inner reader, and the position of the wrapper moves back by their
+    /// number. Bytes returned by a previous call to `fill_buf` should be
+    /// consumed before unreading, as `consume` applies to unread bytes first.
+    /// Unread bytes are discarded when seeking.
+    pub fn unread(&mut self, bytes: &[u8]) {
+        self.unread_bytes(bytes);
+        self.available = 0;
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use partial_io::{PartialOp, PartialRead, PartialWrite};
-    use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
-    use super::Retry;
+impl<T: BufRead, P: RetryPolicy> Retry<T, P> {
+    /// Reads until `byte` is found like `read_until`, failing with an error of
+    /// kind `InvalidData` if this takes more than `max_refills` calls to
+    /// `fill_buf`.
+    ///
+    /// This protects against sources trickling data without ever sending the
+    /// delimiter, independently of the length of the data. The bytes read
+    /// before the error are appended to `buf`.
+    pub fn read_until_max_refills(&mut self, byte: u8, buf: &mut Vec<u8>,
+        max_refills: u32) -> io::Result<usize>
+    {
+        let start = buf.len();
+        for _ in 0..max_refills {
+            let (done, used) = {
+                let available = self.fill_buf()?;
+                match available.iter().position(|&b| b == byte) {
+                    Some(i) => {
+                        buf.extend_from_slice(&available[..=i]);
+                        (true, i + 1)
+                    }
+                    None => {
+                        buf.extend_from_slice(available);
+                        (available.is_empty(), available.len())
+                    }
+                }
+            };
+            self.consume(used);
+            if done {
+                return Ok(buf.len() - start);
+            }
+        }
+        Err(io::Error::new(ErrorKind::InvalidData,
+            "too many refills without finding the delimiter"))
+    }
 
-    #[test]
-    fn reads() {
-        let input = &b"Read test"[..];
-        let ops = vec![PartialOp::Err(ErrorKind::Interrupted)];
-        let mut reader = Retry::new(PartialRead::new(input, ops));
-        let mut out = vec![0u8; input.len()];
-        assert_eq!(reader.read(&mut out).unwrap(), input.len());
-        assert_eq!(&out[..], input);
+    /// Reads a line like `read_line`, failing with an error of kind
+    /// `InvalidData` if the line, including its terminating newline, is longer
+    /// than `max` bytes.
+    ///
+    /// At most `max` bytes are consumed and held in memory. Nothing is
+    /// appended to `buf` if the line is too long.
+    pub fn read_line_limited(&mut self, buf: &mut String, max: usize)
+        -> io::Result<usize>
+    {
+        let mut bytes = Vec::new();
+        let res = loop {
+            let (len, done, too_long) = {
+                let available = match self.fill_buf() {
+                    Ok(available) => available,
+                    Err(e) => break Err(e),
+                };
+                let (line, done) =
+                    match available.iter().position(|&b| b == b'\n') {
+                        Some(i) => (&available[..=i], true),
+                        None => (available, available.is_empty()),
+                    };
+                let len = line.len().min(max - bytes.len());
+                bytes.extend_from_slice(&line[..len]);
+                (len, done, len < line.len())
+            };
+            self.consume(len);
+            if too_long {
+                return Err(io::Error::new(ErrorKind::InvalidData,
+                    "line too long"));
+            }
+            if done {
+                break Ok(bytes.len());
+            }
+        };
+        match String::from_utf8(bytes) {
+            Ok(s) => {
+                buf.push_str(&s);
+                res
+            }
+            Err(_) => res.and_then(|_| Err(invalid_utf8())),
+        }
     }
 
-    #[test]
-    fn reads_buffered() {
-        let input = &b"Read test"[..];
-        let ops = vec![PartialOp::Err(ErrorKind::Interrupted)];
-        let mut reader = Retry::new(BufReader::with_capacity(input.len(),
-            PartialRead::new(input, ops)));
-        assert_eq!(reader.fill_buf().unwrap(), input);
+    /// Reads a line like `read_line`, replacing invalid UTF-8 sequences with
+    /// `U+FFFD REPLACEMENT CHARACTER` instead of failing.
+    ///
+    /// Returns the number of bytes read, which may differ from the number of
+    /// bytes appended to `buf` if replacements were made. The bytes read
+    /// before an error are appended as well.
+    pub fn read_line_lossy(&mut self, buf: &mut String) -> io::Result<usize> {
+        let mut bytes = Vec::new();
+        let res = self.read_until(b'\n', &mut bytes);
+        buf.push_str(&String::from_utf8_lossy(&bytes));
+        res
     }
 
-    #[test]
-    fn writes() {
-        let input = &b"Write test"[..];
-        let ops = vec![PartialOp::Err(ErrorKind::Interrupted)];
-        let mut writer = Retry::new(PartialWrite::new(Vec::<u8>::new(), ops));
-        assert_eq!(writer.write(input).unwrap(), input.len());
-        assert_eq!(&writer.into_inner().into_inner()[..], input);
+    /// Reads a line like `read_line` into `buf`, which is cleared first, and
+    /// returns the number of bytes read, zero at the end of the stream.
+    ///
+    /// Unlike `read_line`, nothing is kept from the previous content of `buf`,
+    /// and the line is read directly into its allocation, so that a single
+    /// buffer can be reused for successive lines. `buf` is left empty if the
+    /// line is not valid UTF-8.
+    pub fn read_line_into(&mut self, buf: &mut String) -> io::Result<usize> {
+        let mut bytes = mem::take(buf).into_bytes();
+        bytes.clear();
+        let res = self.read_until(b'\n', &mut bytes);
+        match String::from_utf8(bytes) {
+            Ok(s) => {
+                *buf = s;
+                res
+            }
+            Err(e) => {
+                let mut bytes = e.into_bytes();
+                bytes.clear();
+                *buf = String::from_utf8(bytes).unwrap_or_default();
+                res.and_then(|_| Err(invalid_utf8()))
+            }
+        }
+    }
+}
+
+impl<T: Seek, P: RetryPolicy> Retry<T, P> {
+    /// Skips up to `n` bytes by seeking instead of reading them, retrying each
+    /// seek like `read`.
+    ///
+    /// Returns the number of bytes skipped, which is less than `n` only if the
+    /// end of the stream was reached.
+    pub fn skip_by_seek(&mut self, n: u64) -> io::Result<u64> {
+        let start = self.stream_position()?;
+        self.discard_buffered();
+        let end = self.retry(|inner| inner.seek(SeekFrom::End(0)))?;
+        let target = start.saturating_add(n).min(end.max(start));
+        self.position = self.retry(|inner| {
+            inner.seek(SeekFrom::Start(target))
+        })?;
+        Ok(target - start)
+    }
+
+    /// Returns the position in the stream as maintained by the wrapper,
+    /// without performing any IO.
+    ///
+    /// The position is set by each seek and call to `stream_position`, and
+    /// advanced by the bytes read, consumed and written through the wrapper.
+    /// Until the first seek or call to `stream_position`, the inner value is
+    /// assumed to start at position 0. Accessing the inner value directly
+    /// makes it stale.
+    pub fn tracked_position(&self) -> u64 {
+        self.position
+    }
+}
+
+impl<T: Write, P: RetryPolicy> Retry<T, P> {
+    /// Sets whether [`replace_inner`](Retry::replace_inner) flushes the wrapper
+    /// before replacing the inner writer, retrying like `flush`.
+    ///
+    /// Flushing requires the inner value to implement `Write`, which is why
+    /// this is a setting of the wrapper, see [`Retry`].
+    pub fn flush_on_replace(mut self, flush: bool) -> Self {
+        self.flush_on_replace = if flush {
+            Some(<Self as Write>::flush)
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Writes a whole buffer like `write_all`, reporting how many bytes were
+    /// written if an error is returned.
+    ///
+    /// This allows resuming the write with the rest of `buf` or reporting
+    /// precisely how far it got.
+    pub fn write_all_ctx(&mut self, buf: &[u8])
+        -> Result<(), PartialWriteError>
+    {
+        let mut written = 0;
+        while written < buf.len() {
+            match self.write(&buf[written..]) {
+                Ok(0) => return Err(PartialWriteError {
+                    error: io::Error::new(ErrorKind::WriteZero,
+                        "failed to write whole buffer"),
+                    bytes_written_before_failure: written,
+                }),
+                Ok(n) => written += n,
+                Err(error) => return Err(PartialWriteError {
+                    error,
+                    bytes_written_before_failure: written,
+                }),
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a whole buffer like `write_all` and reports how the inner writer
+    /// was called to do so.
+    ///
+    /// The buffer is written with `write`, so all the settings of the wrapper
+    /// apply, e.g. coalescing, periodic flushes and ignoring broken pipes.
+    /// The report counts the calls made to the inner writer during this call,
+    /// including the ones writing bytes coalesced earlier. If the inner writer
+    /// keeps accepting 0 bytes, an error of kind `WriteZero` is returned after
+    /// a few attempts.
+    pub fn write_all_reporting(&mut self, buf: &[u8])
+        -> io::Result<WriteReport>
+    {
+        let (calls, retries) = (self.write_calls, self.write_retries);
+        let mut written = 0;
+        let mut zero_writes = 0;
+        while written < buf.len() {
+            match self.write(&buf[written..])? {
+                0 => {
+                    zero_writes += 1;
+                    if zero_writes == MAX_ZERO_WRITES {
+                        return Err(io::Error::new(ErrorKind::WriteZero,
+                            "failed to write whole buffer"));
+                    }
+                }
+                n => {
+                    zero_writes = 0;
+                    written += n;
+                }
+            }
+        }
+        Ok(WriteReport {
+            total_written: written,
+            inner_calls: self.write_calls - calls,
+            retries: self.write_retries - retries,
+        })
+    }
+}
+
+impl<R, P> Retry<BufReader<R>, P> {
+    /// Returns how many bytes can be consumed without reading from the source
+    /// of the inner `BufReader`, without performing any IO.
+    ///
+    /// Unlike [`available`](Retry::available), this does not depend on the
+    /// last call to `fill_buf` and accounts for every byte held by the
+    /// `BufReader` and the wrapper. It requires access to the buffer of the
+    /// inner reader, which is why it is only available for `BufReader`.
+    pub fn buffered_len(&self) -> usize {
+        self.staged.len() + self.inner.buffer().len()
+    }
+}
+
+impl<R: Read, P: RetryPolicy> Retry<BufReader<R>, P> {
+    /// Fills the buffer until it holds as many bytes as the capacity of the
+    /// inner `BufReader` or the end of the stream is reached, retrying like
+    /// `fill_buf`, and returns the buffered bytes.
+    ///
+    /// `BufReader` only reads from its source once its buffer is empty and
+    /// then returns whatever a single `read` produced, which may be far less
+    /// than its capacity. This method keeps reading and moves the bytes into
+    /// a buffer owned by the wrapper, from which they are then served before
+    /// the inner reader is used again. This requires a reader with a fixed
+    /// capacity, which is why it is only available for `BufReader`.
+    pub fn fill_to_capacity(&mut self) -> io::Result<&[u8]> {
+        let capacity = self.inner.capacity();
+        while self.staged.len() < capacity {
+            let refill = self.inner.buffer().is_empty();
+            let len = self.retry_read(|inner| {
+                inner.fill_buf().map(|b| b.len())
+            })?;
+            if refill {
+                self.config.settings.refilled(len);
+            }
+            if len == 0 {
+                break;
+            }
+            let n = len.min(capacity - self.staged.len());
+            self.staged.extend(&self.inner.buffer()[..n]);
+            self.inner.consume(n);
+        }
+        self.available = self.staged.len();
+        Ok(self.staged.make_contiguous())
+    }
+
+    /// Consumes and returns the bytes held by the inner `BufReader` and the
+    /// wrapper, without reading more from the source.
+    ///
+    /// This is meant for handing the source over to other code, e.g. when
+    /// switching protocols on a connection, without losing the bytes already
+    /// buffered. Unlike `read_to_end`, this stops once the buffered bytes
+    /// are consumed: the buffers are emptied with `fill_buf` and `consume`,
+    /// retrying like them, and `fill_buf` is only called while bytes remain
+    /// buffered, so that no new read is forced on the source.
+    pub fn drain_buffer(&mut self) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        while !self.staged.is_empty() || !self.inner.buffer().is_empty() {
+            let buf = self.fill_buf()?;
+            let n = buf.len();
+            bytes.extend_from_slice(buf);
+            self.consume(n);
+        }
+        Ok(bytes)
+    }
+}
+
+impl<T: Write, P: RetryPolicy> Retry<T, P> {
+    fn write_pending(&mut self) -> io::Result<()> {
+        let mut pending = mem::take(&mut self.pending);
+        let mut written = 0;
+        let res = loop {
+            if written == pending.len() {
+                break Ok(());
+            }
+            match self.retry_inner_write(&pending[written..]) {
+                Ok(0) => break Err(io::Error::new(ErrorKind::WriteZero,
+                    "failed to write pending bytes")),
+                Ok(n) => written += n,
+                Err(e) => break Err(e),
+            }
+        };
+        pending.drain(..written);
+        self.pending = pending;
+        res
+    }
+
+    fn write_coalesced(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.flush_if_due()?;
+        match self.config.settings.coalesce_threshold {
+            Some(threshold) => {
+                let capacity = self.config.settings.max_buffer
+                    .map_or(threshold, |max| max.min(threshold));
+                if self.pending.len() + buf.len() > capacity {
+                    self.write_pending()?;
+                }
+                if buf.len() < threshold && capacity > 0 {
+                    // Lets seeking, which does not require `T: Write`, write
+                    // the pending bytes first.
+                    self.write_before_seek = Some(Self::write_pending);
+                    let n = buf.len().min(capacity - self.pending.len());
+                    self.pending.extend_from_slice(&buf[..n]);
+                    Ok(n)
+                } else {
+                    self.retry_inner_write(buf)
+                }
+            }
+            None => self.retry_inner_write(buf),
+        }
+    }
+
+    fn retry_inner_write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut calls = 0;
+        let res = self.retry_write(|inner| {
+            calls += 1;
+            inner.write(buf)
+        });
+        self.write_calls += calls;
+        self.write_retries += calls - 1;
+        res
+    }
+
+    fn broken_pipe(&mut self) {
+        self.pending.clear();
+        if !mem::replace(&mut self.broken_pipe_reported, true) {
+            if let Some(ref hook) = self.config.settings.on_broken_pipe {
+                hook.with(|f| f());
+            }
+        }
+    }
+
+    fn flush_if_due(&mut self) -> io::Result<()> {
+        match self.config.settings.flush_every {
+            Some(bytes) if self.unflushed >= bytes => self.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<W: Write, P: RetryPolicy> Retry<BufWriter<W>, P> {
+    /// Flushes the inner `BufWriter`, retrying the errors approved by the
+    /// policy until its buffer is empty.
+    ///
+    /// `BufWriter` already retries `Interrupted` while flushing but gives up
+    /// on any other error, leaving the unwritten bytes in its buffer. This
+    /// method keeps pushing them through as long as the policy allows.
+    pub fn flush_buffer_with_retry(&mut self) -> io::Result<()> {
+        while !self.inner.buffer().is_empty() {
+            self.retry_write(|inner| inner.flush())?;
+        }
+        Ok(())
+    }
+}
+
+/// Copies the rest of `src` to `dst` and returns the number of bytes copied.
+///
+/// Unlike `io::copy`, which calls whichever methods of its arguments it sees
+/// fit, each chunk is read with the retrying `read` of `src` and then written
+/// in full with the retrying `write_all` of `dst`, even if `dst` accepts it in
+/// several short writes, before the next chunk is read. Both sides thus retry
+/// according to their own settings.
+pub fn pipe<R, P, W, Q>(src: &mut Retry<R, P>, dst: &mut Retry<W, Q>)
+    -> io::Result<u64>
+where
+    R: Read,
+    P: RetryPolicy,
+    W: Write,
+    Q: RetryPolicy,
+{
+    let mut chunk = [0u8; CHUNK_SIZE];
+    let mut copied = 0;
+    loop {
+        match src.read(&mut chunk)? {
+            0 => return Ok(copied),
+            n => {
+                dst.write_all(&chunk[..n])?;
+                copied += n as u64;
+            }
+        }
+    }
+}
+
+/// Returns an iterator skipping the errors yielded by `iter` that the policy
+/// of `builder` decides to retry, pulling the next item instead.
+///
+/// This brings retries to streams of results, e.g. of decoded frames, where
+/// an error such as `Interrupted` does not end the stream. The number of
+/// retries given to the policy and its backoff apply to consecutive errors
+/// and are reset by each item yielded. The backoff is waited through the
+/// clock of `builder`, see [`RetryBuilder::clock`]. Other settings of
+/// `builder` are ignored.
+///
+/// ```
+/// use retrio::RetryBuilder;
+/// use std::io::{ErrorKind, self};
+///
+/// let items = vec![Ok(1), Err(ErrorKind::Interrupted.into()), Ok(2)];
+/// let items = retrio::retry_results(items, RetryBuilder::new())
+///     .collect::<io::Result<Vec<i32>>>()
+///     .unwrap();
+/// assert_eq!(items, [1, 2]);
+/// ```
+pub fn retry_results<I, T, P>(iter: I, builder: RetryBuilder<P>)
+    -> RetryResults<I::IntoIter, P>
+where
+    I: IntoIterator<Item = io::Result<T>>,
+    P: RetryPolicy,
+{
+    RetryResults {iter: iter.into_iter(), config: builder.config}
+}
+
+/// Calls `connect` until it succeeds or fails with an error that the policy
+/// of `builder` does not retry, and wraps the connection it returns with the
+/// settings of `builder`.
+///
+/// This makes establishing a connection, e.g. with `TcpStream::connect`, as
+/// resilient as using it. The backoff of the policy is waited between
+/// attempts, through the clock of `builder` if one is set with
+/// [`RetryBuilder::clock`]. If all attempts fail, the last error is returned.
+///
+/// ```
+/// use retrio::RetryBuilder;
+/// use retrio::policy::DefaultPolicy;
+/// use std::io::ErrorKind;
+/// use std::net::{TcpListener, TcpStream};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let listener = TcpListener::bind("127.0.0.1:0")?;
+/// let addr = listener.local_addr()?;
+/// let policy = DefaultPolicy::new()
+///     .retry_on(ErrorKind::ConnectionRefused)
+///     .max_retries(3);
+/// let builder = RetryBuilder::new().policy(policy);
+/// let stream = retrio::retry_connect(builder, || TcpStream::connect(addr))?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn retry_connect<A, P, F>(builder: RetryBuilder<P>, mut connect: F)
+    -> io::Result<Retry<A, P>>
+where
+    P: RetryPolicy,
+    F: FnMut() -> io::Result<A>,
+{
+    let mut retries = 0;
+    loop {
+        match connect() {
+            Ok(conn) => return Ok(builder.build(conn)),
+            Err(e) if builder.config.policy.should_retry(&e, retries) => {
+                let delay = builder.config.policy.backoff(&e, retries);
+                if delay > Duration::from_secs(0) {
+                    builder.config.settings.sleep(delay);
+                }
+                retries += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Iterator returned by [`retry_results`].
+#[derive(Clone, Debug)]
+pub struct RetryResults<I, P> {
+    iter: I,
+    config: RetryConfig<P>,
+}
+
+impl<I, T, P> Iterator for RetryResults<I, P>
+where
+    I: Iterator<Item = io::Result<T>>,
+    P: RetryPolicy,
+{
+    type Item = io::Result<T>;
+
+    fn next(&mut self) -> Option<io::Result<T>> {
+        let mut retries = 0;
+        loop {
+            match self.iter.next()? {
+                Err(e) if self.config.policy.should_retry(&e, retries) => {
+                    let delay = self.config.policy.backoff(&e, retries);
+                    if delay > Duration::from_secs(0) {
+                        self.config.settings.sleep(delay);
+                    }
+                    retries += 1;
+                }
+                item => return Some(item),
+            }
+        }
+    }
+}
+
+/// Direct access to the inner value of a wrapper, returned by
+/// [`Retry::raw_scope`].
+#[derive(Debug)]
+pub struct RawGuard<'a, T>(&'a mut T);
+
+impl<T> Deref for RawGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.0
+    }
+}
+
+impl<T> DerefMut for RawGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.0
+    }
+}
+
+/// Counters of a wrapper, returned by [`Retry::take_stats`].
+#[cfg(feature = "stats")]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct RetryStats {
+    /// Number of operations, see [`Retry::operation_count`].
+    pub operations: u64,
+    /// Number of retries performed.
+    pub retries: u64,
+    /// Number of bytes read or consumed.
+    pub bytes_read: u64,
+    /// Number of bytes written.
+    pub bytes_written: u64,
+    /// Kind of the last error returned by the inner value during the last
+    /// operation, if any.
+    pub last_error: Option<ErrorKind>,
+}
+
+/// Settings replacing the ones of a wrapper for a single call to
+/// [`Retry::read_with`].
+///
+/// Settings left to `None` are the ones of the wrapper.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct RetryOverrides {
+    /// Maximum number of attempts, including the first one. `Some(1)`
+    /// disables retrying.
+    pub max_attempts: Option<u32>,
+    /// How long after the start of the call errors stop being retried, in
+    /// addition to the deadlines of the wrapper.
+    pub deadline: Option<Duration>,
+    /// Kinds of errors retried instead of the ones retried by the policy of
+    /// the wrapper. Backoffs are still given by the policy, and reconnecting,
+    /// rewinding, waiting for readiness and the
+    /// [startup grace](RetryBuilder::startup_grace) still apply.
+    pub kinds: Option<Vec<ErrorKind>>,
+}
+
+/// Statistics about a call to [`Retry::write_all_reporting`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct WriteReport {
+    /// Number of bytes written.
+    pub total_written: usize,
+    /// Number of calls to the `write` method of the inner writer.
+    pub inner_calls: u64,
+    /// Number of calls to the inner writer that failed and were retried.
+    pub retries: u64,
+}
+
+/// Error returned by [`Retry::write_all_ctx`].
+#[derive(Debug)]
+pub struct PartialWriteError {
+    /// Error that stopped the write.
+    pub error: io::Error,
+    /// Number of bytes successfully written before the error occurred.
+    pub bytes_written_before_failure: usize,
+}
+
+impl fmt::Display for PartialWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} after writing {} bytes", self.error,
+            self.bytes_written_before_failure)
+    }
+}
+
+impl Error for PartialWriteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<PartialWriteError> for io::Error {
+    fn from(e: PartialWriteError) -> Self {
+        e.error
+    }
+}
+
+impl<T: TakeBuffer, P> Retry<T, P> {
+    /// Takes the bytes written so far to the inner buffer, leaving it empty so
+    /// that the wrapper can be reused.
+    pub fn take_buffer(&mut self) -> Vec<u8> {
+        self.inner.take_buffer()
+    }
+}
+
+/// In-memory writers whose content can be extracted without consuming them.
+pub trait TakeBuffer {
+    /// Takes the bytes written so far, leaving the writer empty.
+    fn take_buffer(&mut self) -> Vec<u8>;
+}
+
+impl TakeBuffer for Vec<u8> {
+    fn take_buffer(&mut self) -> Vec<u8> {
+        mem::take(self)
+    }
+}
+
+impl<T: SocketTimeouts, P> Retry<T, P> {
+    /// Sets the read timeout of the inner socket.
+    ///
+    /// Reads that time out fail with an error of kind `WouldBlock` on Unix and
+    /// `TimedOut` on Windows, which is retried if the policy says so. The read
+    /// deadline of wrappers made with [`Retry::from_socket`] is not updated.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>)
+        -> io::Result<()>
+    {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    /// Sets the write timeout of the inner socket.
+    ///
+    /// Writes that time out fail like reads, see
+    /// [`set_read_timeout`](Retry::set_read_timeout).
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>)
+        -> io::Result<()>
+    {
+        self.inner.set_write_timeout(timeout)
+    }
+}
+
+/// Sockets whose read and write timeouts can be set.
+pub trait SocketTimeouts {
+    /// Sets the read timeout, `None` meaning reads block indefinitely.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Sets the write timeout, `None` meaning writes block indefinitely.
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl SocketTimeouts for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
+
+impl SocketTimeouts for UdpSocket {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UdpSocket::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UdpSocket::set_write_timeout(self, timeout)
+    }
+}
+
+/// Builder to configure a [`Retry`] wrapper.
+///
+/// By default, the wrapper uses a [`DefaultPolicy`] retrying only errors of
+/// kind `Interrupted`, as many times as needed and without waiting between
+/// attempts.
+#[derive(Clone, Debug, Default)]
+pub struct RetryBuilder<P = DefaultPolicy> {
+    config: RetryConfig<P>,
+}
+
+impl RetryBuilder {
+    /// Returns a builder with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Retries errors of the given kind in addition to the ones already
+    /// configured.
+    pub fn retry_on(mut self, kind: ErrorKind) -> Self {
+        self.config.policy = self.config.policy.retry_on(kind);
+        self
+    }
+
+    /// Retries errors of the given kinds only, replacing the ones already
+    /// configured.
+    pub fn kinds<I>(mut self, kinds: I) -> Self
+    where
+        I: IntoIterator<Item = ErrorKind>,
+    {
+        self.config.policy = self.config.policy.kinds(kinds);
+        self
+    }
+
+    /// Retries errors with the given raw OS error code in addition to the ones
+    /// already configured.
+    pub fn retry_on_os_error(mut self, code: i32) -> Self {
+        self.config.policy = self.config.policy.retry_on_os_error(code);
+        self
+    }
+
+    /// Gives up after `n` retries of the same operation.
+    pub fn max_retries(mut self, n: u32) -> Self {
+        self.config.policy = self.config.policy.max_retries(n);
+        self
+    }
+
+    /// Sets how long to wait before each retry.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.config.policy = self.config.policy.backoff(backoff);
+        self
+    }
+
+    /// Replaces the retried kinds, maximum number of retries and backoff with
+    /// the ones of a preset profile.
+    pub fn profile(mut self, profile: RetryProfile) -> Self {
+        self.config.policy = profile.into();
+        self
+    }
+}
+
+impl<P> RetryBuilder<P> {
+    /// Replaces the policy deciding which errors are retried.
+    pub fn policy<Q: RetryPolicy>(self, policy: Q) -> RetryBuilder<Q> {
+        RetryBuilder {
+            config: RetryConfig {policy, settings: self.config.settings},
+        }
+    }
+
+    /// Also retries errors wrapping an error of type `E` for which `f` returns
+    /// `true`, e.g. errors made with `io::Error::new(ErrorKind::Other, e)`.
+    ///
+    /// The current policy is combined with a
+    /// [`DowncastPolicy`] through
+    /// [`RetryPolicy::or`], so this should be called after the methods
+    /// configuring the default policy.
+    ///
+    /// ```
+    /// use retrio::RetryBuilder;
+    /// use std::fmt;
+    ///
+    /// #[derive(Debug)]
+    /// struct Busy;
+    ///
+    /// impl fmt::Display for Busy {
+    ///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    ///         f.write_str("busy")
+    ///     }
+    /// }
+    ///
+    /// impl std::error::Error for Busy {}
+    ///
+    /// let builder = RetryBuilder::new()
+    ///     .max_retries(3)
+    ///     .retry_if_downcasts(|_: &Busy| true);
+    /// ```
+    pub fn retry_if_downcasts<E, F>(self, f: F)
+        -> RetryBuilder<Or<P, DowncastPolicy<E, F>>>
+    where
+        P: RetryPolicy,
+        E: Error + 'static,
+        F: Fn(&E) -> bool,
+    {
+        let policy = self.config.policy.or(policy::downcast(f));
+        RetryBuilder {
+            config: RetryConfig {policy, settings: self.config.settings},
+        }
+    }
+
+    /// Sets whether the current thread yields to other threads every few
+    /// consecutive retries of an operation, 8 by default.
+    ///
+    /// Retrying without backoff spins, which may keep other threads from
+    /// running on a busy or single-core machine. Yielding gives the scheduler
+    /// a chance to run them without slowing down operations that only need a
+    /// retry or two. Retries that wait for a backoff already let other threads
+    /// run and do not yield. The interval can be changed with
+    /// [`yield_every`](RetryBuilder::yield_every).
+    pub fn fair(mut self, fair: bool) -> Self {
+        self.config.settings.yield_every = if fair {
+            Some(DEFAULT_YIELD_INTERVAL)
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Yields to other threads every `k` consecutive retries of an operation,
+    /// like [`fair`](RetryBuilder::fair) but with a custom interval.
+    pub fn yield_every(mut self, k: u32) -> Self {
+        self.config.settings.yield_every = Some(k);
+        self
+    }
+
+    /// Transforms the errors given up on with `map` before returning them.
+    ///
+    /// This adapts the errors of the wrapper to code expecting other kinds,
+    /// e.g. by turning `TimedOut` into `WouldBlock`. Errors being retried are
+    /// not transformed, and the hook set with
+    /// [`on_give_up`](RetryBuilder::on_give_up) and [`Retry::last_error`] see
+    /// the original error. Errors made up by the wrapper itself, like
+    /// `UnexpectedEof` from `read_exact`, are not transformed either.
+    pub fn map_error(mut self, map: fn(io::Error) -> io::Error) -> Self {
+        self.config.settings.map_error = Some(map);
+        self
+    }
+
+    /// Calls `f` whenever an error is about to be returned because the policy
+    /// gave up on retrying it, be it because of its kind or because the
+    /// maximum number of retries was reached.
+    ///
+    /// `f` receives the error and the total number of attempts of the failed
+    /// operation. It is not called for errors that are retried nor for
+    /// operations that eventually succeed.
+    pub fn on_give_up<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&io::Error, u32) + Send + 'static,
+    {
+        self.config.settings.on_give_up = Some(Hook(Arc::new(Mutex::new(f))));
+        self
+    }
+
+    /// Calls `f` before each retry with details about why it happens.
+    pub fn on_retry<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&RetryInfo) + Send + 'static,
+    {
+        self.config.settings.on_retry = Some(Hook(Arc::new(Mutex::new(f))));
+        self
+    }
+
+    /// Calls `f` whenever an operation succeeds after being retried.
+    ///
+    /// `f` receives the number of retries the operation took. It is not called
+    /// for operations that succeed on their first attempt, which makes it
+    /// suitable to reset a circuit breaker tripped by
+    /// [`on_give_up`](RetryBuilder::on_give_up).
+    pub fn on_recovered<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(u32) + Send + 'static,
+    {
+        self.config.settings.on_recovered = Some(Hook(Arc::new(Mutex::new(f))));
+        self
+    }
+
+    /// Calls `f` whenever `fill_buf` refills the buffer of the inner reader
+    /// instead of returning bytes it already holds.
+    ///
+    /// `f` receives the number of bytes the refill obtained, zero at the end
+    /// of the stream, and is called once per refill however many times it was
+    /// retried. [`Retry::fill_to_capacity`] reports each refill it performs
+    /// as well. Bytes buffered by the wrapper itself, e.g. by
+    /// [`Retry::unread`], do not count as refills.
+    ///
+    /// A generic `BufRead` does not tell whether its buffer is empty, so
+    /// `fill_buf` counts as a refill exactly when every byte returned by the
+    /// previous `fill_buf` was consumed through the wrapper. Other reads are
+    /// not tracked: a refill performed by `read`, which `BufReader` does when
+    /// its buffer is empty, is not reported, and the `fill_buf` returning the
+    /// rest of the bytes it buffered is then reported instead. The same goes
+    /// for reads made on the inner reader directly.
+    pub fn on_refill<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(usize) + Send + 'static,
+    {
+        self.config.settings.on_refill = Some(Hook(Arc::new(Mutex::new(f))));
+        self
+    }
+
+    /// Stops retrying a read once `timeout` has elapsed since its first
+    /// attempt, and returns the last error.
+    ///
+    /// The backoff before a retry is shortened so as not to wait past the
+    /// deadline. An inner read that blocks is not interrupted.
+    pub fn read_deadline(mut self, timeout: Duration) -> Self {
+        self.config.settings.read_deadline = Some(timeout);
+        self
+    }
+
+    /// Stops retrying a write once `timeout` has elapsed since its first
+    /// attempt, and returns the last error.
+    ///
+    /// The backoff before a retry is shortened so as not to wait past the
+    /// deadline. An inner write that blocks is not interrupted.
+    pub fn write_deadline(mut self, timeout: Duration) -> Self {
+        self.config.settings.write_deadline = Some(timeout);
+        self
+    }
+
+    /// Limits how often the [`on_retry`](RetryBuilder::on_retry) hook is
+    /// called when the same error is retried over and over.
+    ///
+    /// The hook is always called for the first retry of a series of retries of
+    /// the same kind of error for the same reason. Further retries of the
+    /// series are only reported as allowed by `coalescing`, and
+    /// [`RetryInfo::suppressed`] tells how many were skipped since the previous
+    /// call. When the operation ends with retries left unreported, the hook is
+    /// called one last time for the last of them, with an error made up from
+    /// the kind of the original one.
+    pub fn coalesce_retry_events(mut self, coalescing: RetryEventCoalescing)
+        -> Self
+    {
+        self.config.settings.retry_event_coalescing = Some(coalescing);
+        self
+    }
+
+    /// Coalesces writes smaller than `threshold` bytes in a buffer owned by
+    /// the wrapper, which is written to the inner writer once it would grow
+    /// past `threshold` bytes or when the wrapper is flushed.
+    ///
+    /// This saves calls to the inner writer, like wrapping it in a
+    /// `BufWriter`, but pending bytes are written with the same retries,
+    /// budget and hooks as any other write, and `flush` keeps writing them
+    /// until they have all been accepted or an error is given up on. Bytes
+    /// that could not be written remain pending. Pending bytes are written
+    /// before seeking, but not when the wrapper is dropped, so the wrapper
+    /// should be flushed first.
+    pub fn coalesce(mut self, threshold: usize) -> Self {
+        self.config.settings.coalesce_threshold = Some(threshold);
+        self
+    }
+
+    /// Limits the buffer used to [`coalesce`](RetryBuilder::coalesce) writes
+    /// to `capacity` bytes, which otherwise holds up to the coalescing
+    /// threshold.
+    ///
+    /// Once the buffer is full, `write` blocks while writing it to the inner
+    /// writer, retrying like any other write, before accepting more bytes.
+    /// Writes that do not fit in the buffer are accepted partially, so a slow
+    /// inner writer holds back its callers instead of making the buffer grow.
+    /// A capacity of zero disables coalescing.
+    pub fn max_buffer(mut self, capacity: usize) -> Self {
+        self.config.settings.max_buffer = Some(capacity);
+        self
+    }
+
+    /// Serves reads into buffers smaller than `capacity` bytes from a buffer
+    /// owned by the wrapper, which is refilled with a single retrying read of
+    /// up to `capacity` bytes from the inner reader once it is empty.
+    ///
+    /// This saves calls to inner readers that are only efficient with large
+    /// reads, like wrapping them in a `BufReader`, while the buffer is shared
+    /// with [`Retry::unread`] and [`Retry::fill_buf`]. Larger reads bypass
+    /// the buffer once it is empty. Buffered bytes are not accounted as read,
+    /// e.g. by [`Retry::tracked_position`], until they are returned by `read`.
+    pub fn read_buffer(mut self, capacity: usize) -> Self {
+        self.config.settings.read_buffer = Some(capacity);
+        self
+    }
+
+    /// Sets whether `flush` does nothing when no bytes were written through the
+    /// wrapper since the last successful flush.
+    ///
+    /// This saves calls to the inner writer for callers flushing
+    /// defensively. Bytes written to the inner writer directly are not taken
+    /// into account.
+    pub fn skip_redundant_flush(mut self, skip: bool) -> Self {
+        self.config.settings.skip_redundant_flush = skip;
+        self
+    }
+
+    /// Flushes the wrapper once `bytes` bytes have been written through it
+    /// since the last successful flush.
+    ///
+    /// This bounds how much data sits in the buffers of the inner writer, e.g.
+    /// a `BufWriter`. The flush happens at the end of the `write` call that
+    /// reaches the threshold and is retried like any other flush. If it fails
+    /// anyway, that `write` still reports the bytes it wrote and the flush is
+    /// attempted again at the start of the next `write`, which returns the
+    /// error if it fails again.
+    pub fn flush_every(mut self, bytes: u64) -> Self {
+        self.config.settings.flush_every = Some(bytes);
+        self
+    }
+
+    /// Sets whether errors of kind `BrokenPipe` from writing and flushing are
+    /// ignored, making `write` report the whole buffer as written.
+    ///
+    /// This is meant for writers whose reader may go away without it being an
+    /// error, e.g. a pager quit by the user. **Bytes written after the pipe
+    /// broke are silently lost**, including bytes coalesced by the wrapper.
+    /// They are still counted as written, e.g. by
+    /// [`Retry::tracked_position`], and the errors are neither given up on
+    /// nor mapped with [`map_error`](RetryBuilder::map_error). Retrying other
+    /// errors is unaffected.
+    pub fn ignore_broken_pipe(mut self, ignore: bool) -> Self {
+        self.config.settings.ignore_broken_pipe = ignore;
+        self
+    }
+
+    /// Calls `f` the first time an error of kind `BrokenPipe` is ignored by a
+    /// wrapper, see [`ignore_broken_pipe`](RetryBuilder::ignore_broken_pipe).
+    pub fn on_broken_pipe<F>(mut self, f: F) -> Self
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.config.settings.on_broken_pipe =
+            Some(Hook(Arc::new(Mutex::new(f))));
+        self
+    }
+
+    /// Retries up to `n` times when `fill_buf` returns an empty buffer, which
+    /// normally signals the end of the stream.
+    ///
+    /// This is useful to follow a source that is still being written to. The
+    /// backoff of the policy applies between attempts. Like other retries,
+    /// these are bounded by the deadlines,
+    /// [`max_backoff_total`](RetryBuilder::max_backoff_total), the total
+    /// budget and escalation.
+    pub fn retry_empty_fill(mut self, n: u32) -> Self {
+        self.config.settings.empty_fill_retries = n;
+        self
+    }
+
+    /// Retries up to `max` times when `read_exact` reaches the end of the
+    /// stream before filling its buffer, waiting according to `backoff`
+    /// before each attempt, instead of failing with `UnexpectedEof`.
+    ///
+    /// This is useful for `tail -f`-like readers, for which the end of a file
+    /// that is still being written to is only temporary. `max` bounds the
+    /// consecutive attempts finding the end of the stream, and is reset by
+    /// each read making progress. Like other retries, these are bounded by
+    /// the [read deadline](RetryBuilder::read_deadline), counted from the
+    /// first attempt finding the end of the stream, by
+    /// [`max_backoff_total`](RetryBuilder::max_backoff_total), the total
+    /// budget and escalation. Methods built on `read_exact`, like
+    /// [`Retry::read_exact_vec`], are affected as well, whereas `read` keeps
+    /// returning 0 at the end of the stream.
+    pub fn retry_eof(mut self, max: u32, backoff: Backoff) -> Self {
+        self.config.settings.retry_eof = Some((max, backoff));
+        self
+    }
+
+    /// Shares a budget of `n` retries between all the operations performed
+    /// over the lifetime of the wrapper, be they reads or writes.
+    ///
+    /// Each retry consumes one unit of the budget. Once it is exhausted, errors
+    /// are returned instead of being retried, even if the policy would retry
+    /// them, until the budget is replenished with [`Retry::refill_budget`].
+    pub fn total_budget(mut self, n: u64) -> Self {
+        self.config.settings.total_budget = Some(n);
+        self
+    }
+
+    /// Calls `hook` once operations have kept being retried for `quiet_period`
+    /// without any of them succeeding, and acts on what it returns.
+    ///
+    /// This is meant for streams where short bursts of errors are expected
+    /// but persistent ones call for action. The period starts with the first
+    /// retry after a successful operation, and starts over whenever `hook` is
+    /// called, so that `hook` is called again if retries persist. Settings
+    /// that stop retries, like the total budget, take precedence.
+    pub fn escalate_after<F>(mut self, quiet_period: Duration, hook: F)
+        -> Self
+    where
+        F: FnMut() -> Escalate + Send + 'static,
+    {
+        self.config.settings.escalation =
+            Some((quiet_period, Hook(Arc::new(Mutex::new(hook)))));
+        self
+    }
+
+    /// Fails reads with an error of kind `TimedOut` once less than
+    /// `bytes_per_sec` bytes per second were read on average over the last
+    /// `window`.
+    ///
+    /// This catches transfers that keep making progress, or keep being
+    /// retried, but far too slowly. The throughput is checked before each
+    /// attempt to read, including retries, starting one `window` after the
+    /// first read. Bytes already read are thus never lost to this error. Time
+    /// spent between reads counts as well, so this is meant for transfers that
+    /// read continuously.
+    pub fn min_throughput(mut self, bytes_per_sec: u64, window: Duration)
+        -> Self
+    {
+        self.config.settings.min_throughput = Some((bytes_per_sec, window));
+        self
+    }
+
+    /// Retries all errors during `grace` after the wrapper is created, before
+    /// falling back to the policy.
+    ///
+    /// This is meant for resources that are briefly unreliable right after
+    /// being opened, e.g. a device settling. Errors retried this way are
+    /// reported as [`RetryReason::StartupGrace`] and wait for the backoff of
+    /// the policy. The retry limit of the policy, the total budget and
+    /// deadlines still apply, so that an operation that keeps failing does
+    /// not retry for the whole grace period. The grace period also applies
+    /// to the errors not retried by [`RetryOverrides::kinds`].
+    pub fn startup_grace(mut self, grace: Duration) -> Self {
+        self.config.settings.startup_grace = Some(grace);
+        self
+    }
+
+    /// Gives up on an operation rather than waiting for a backoff that would
+    /// bring the total time spent waiting between its attempts past `max`.
+    ///
+    /// Unlike the deadlines, this ignores the time spent performing IO, which
+    /// bounds the cost of the backoff when the IO itself is fast.
+    pub fn max_backoff_total(mut self, max: Duration) -> Self {
+        self.config.settings.max_backoff_total = Some(max);
+        self
+    }
+
+    /// Uses `clock` to measure durations and to wait between retries, instead
+    /// of [`SystemClock`].
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.config.settings.clock = Some(SharedClock(Arc::new(clock)));
+        self
+    }
+
+    /// Sends [`RetryEvent`]s about retries, errors given up on and bytes read
+    /// to `sender`.
+    ///
+    /// This lets another thread, e.g. the UI thread of an application, follow
+    /// the progress of the wrapper instead of running hooks on the IO thread.
+    /// Events are sent without blocking: they are dropped if the receiver is
+    /// gone and, for a bounded channel, if it is full.
+    pub fn events<S>(mut self, sender: S) -> Self
+    where
+        S: EventSender + Send + 'static,
+    {
+        let send = move |event| sender.send_event(event);
+        self.config.settings.events = Some(Hook(Arc::new(Mutex::new(send))));
+        self
+    }
+
+    /// Calls `waiter` with the kind of operation that failed with
+    /// `WouldBlock` and retries the operation once it returns.
+    ///
+    /// `waiter` is expected to block until the inner value is ready, e.g. with
+    /// `poll` or `select`, which turns the wrapper into a blocking shim over a
+    /// non-blocking IO object. Errors of kind `WouldBlock` from reading and
+    /// writing are then handled regardless of the policy, but each wait counts
+    /// as a retry against the total budget. If `waiter` fails, its error is
+    /// returned.
+    pub fn waiter<F>(mut self, waiter: F) -> Self
+    where
+        F: FnMut(Interest) -> io::Result<()> + Send + 'static,
+    {
+        self.config.settings.waiter = Some(Hook(Arc::new(Mutex::new(waiter))));
+        self
+    }
+
+    /// Wraps a value with the configured settings.
+    pub fn build<T>(self, inner: T) -> Retry<T, P> {
+        Retry::with_config(inner, self.build_config())
+    }
+
+    /// Returns the configured settings, to be shared by many wrappers with
+    /// [`Retry::with_config`].
+    pub fn build_config(self) -> Arc<RetryConfig<P>> {
+        Arc::new(self.config)
+    }
+}
+
+/// Preset bundles of retry settings.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RetryProfile {
+    /// Retries errors of kind `Interrupted`, `WouldBlock` and `TimedOut` up to
+    /// 10 times per operation, waiting 1ms before the first retry and doubling
+    /// the wait for each subsequent retry up to 100ms.
+    Aggressive,
+    /// Retries errors of kind `Interrupted` only, up to 3 times per operation,
+    /// without waiting between attempts.
+    Conservative,
+    /// Never retries. Errors of all kinds, including `Interrupted`, are
+    /// returned as is.
+    None,
+}
+
+/// How often retries of the same error are reported.
+///
+/// See [`RetryBuilder::coalesce_retry_events`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RetryEventCoalescing {
+    /// Reports one retry out of every `n` consecutive retries of the same
+    /// error.
+    Count(u32),
+    /// Reports at most one retry of the same error per interval.
+    Interval(Duration),
+}
+
+/// Event sent to the channel set with [`RetryBuilder::events`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RetryEvent {
+    /// An operation failed and is about to be retried.
+    Retried {
+        /// Kind of the error retried.
+        kind: ErrorKind,
+        /// Number of the retry, starting at 1 for each operation.
+        attempt: u32,
+    },
+    /// Bytes were read or consumed.
+    Progress {
+        /// Total number of bytes read or consumed through the wrapper so far.
+        total: u64,
+    },
+    /// An error was given up on and is returned.
+    GaveUp {
+        /// Kind of the error returned.
+        kind: ErrorKind,
+    },
+}
+
+/// Sending half of a channel of [`RetryEvent`]s.
+///
+/// Events are sent without blocking and dropped if they cannot be sent.
+pub trait EventSender {
+    /// Sends `event` if possible.
+    fn send_event(&self, event: RetryEvent);
+}
+
+impl EventSender for Sender<RetryEvent> {
+    fn send_event(&self, event: RetryEvent) {
+        let _ = self.send(event);
+    }
+}
+
+impl EventSender for SyncSender<RetryEvent> {
+    fn send_event(&self, event: RetryEvent) {
+        let _ = self.try_send(event);
+    }
+}
+
+/// What to do when retries persist, returned by the hook set with
+/// [`RetryBuilder::escalate_after`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Escalate {
+    /// Keeps retrying.
+    Continue,
+    /// Gives up and returns the last error.
+    Fail,
+    /// Replaces the inner value using the factory set with
+    /// [`Retry::reconnect_on`] and retries. Gives up like `Fail` if there is
+    /// no such factory.
+    Reconnect,
+}
+
+/// Wait between consecutive attempts of an operation.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Backoff {
+    /// Retries immediately.
+    #[default]
+    None,
+    /// Waits the same duration before each retry.
+    Constant(Duration),
+    /// Waits `initial` before the first retry and doubles the wait for each
+    /// subsequent retry, without exceeding `max`.
+    Exponential {
+        /// Wait before the first retry.
+        initial: Duration,
+        /// Maximum wait.
+        max: Duration,
+    },
+}
+
+impl Backoff {
+    /// Returns how long to wait before the retry following `retries` previous
+    /// retries of the same operation.
+    pub fn delay(&self, retries: u32) -> Duration {
+        match *self {
+            Backoff::None => Duration::from_secs(0),
+            Backoff::Constant(d) => d,
+            Backoff::Exponential {initial, max} => 2u32.checked_pow(retries)
+                .and_then(|factor| initial.checked_mul(factor))
+                .map_or(max, |d| d.min(max)),
+        }
+    }
+}
+
+type GiveUpHook = Hook<dyn FnMut(&io::Error, u32) + Send>;
+type RetryHook = Hook<dyn for<'a> FnMut(&RetryInfo<'a>) + Send>;
+type RecoveredHook = Hook<dyn FnMut(u32) + Send>;
+type RefillHook = Hook<dyn FnMut(usize) + Send>;
+type WaitHook = Hook<dyn FnMut(Interest) -> io::Result<()> + Send>;
+type BrokenPipeHook = Hook<dyn FnMut() + Send>;
+type EventHook = Hook<dyn FnMut(RetryEvent) + Send>;
+type EscalateHook = Hook<dyn FnMut() -> Escalate + Send>;
+type ReconnectHook<T> = Hook<dyn FnMut() -> io::Result<T> + Send>;
+
+/// Settings of a [`Retry`] wrapper, which can be shared by many wrappers.
+///
+/// See [`Retry::with_config`].
+#[derive(Clone, Debug, Default)]
+pub struct RetryConfig<P = DefaultPolicy> {
+    policy: P,
+    settings: Settings,
+}
+
+impl RetryConfig {
+    /// Returns a builder to configure the settings.
+    ///
+    /// Finish it with [`RetryBuilder::build_config`].
+    pub fn builder() -> RetryBuilder {
+        RetryBuilder::new()
+    }
+}
+
+impl<P> RetryConfig<P> {
+    /// Returns the retry policy.
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Settings {
+    on_give_up: Option<GiveUpHook>,
+    on_retry: Option<RetryHook>,
+    on_recovered: Option<RecoveredHook>,
+    on_refill: Option<RefillHook>,
+    total_budget: Option<u64>,
+    empty_fill_retries: u32,
+    retry_eof: Option<(u32, Backoff)>,
+    waiter: Option<WaitHook>,
+    read_deadline: Option<Duration>,
+    write_deadline: Option<Duration>,
+    coalesce_threshold: Option<usize>,
+    max_buffer: Option<usize>,
+    read_buffer: Option<usize>,
+    skip_redundant_flush: bool,
+    flush_every: Option<u64>,
+    map_error: Option<fn(io::Error) -> io::Error>,
+    yield_every: Option<u32>,
+    escalation: Option<(Duration, EscalateHook)>,
+    min_throughput: Option<(u64, Duration)>,
+    startup_grace: Option<Duration>,
+    clock: Option<SharedClock>,
+    max_backoff_total: Option<Duration>,
+    ignore_broken_pipe: bool,
+    on_broken_pipe: Option<BrokenPipeHook>,
+    events: Option<EventHook>,
+    retry_event_coalescing: Option<RetryEventCoalescing>,
+}
+
+impl Settings {
+    fn now(&self) -> Instant {
+        self.clock.as_ref().map_or_else(Instant::now, |clock| clock.0.now())
+    }
+
+    fn sleep(&self, duration: Duration) {
+        match self.clock {
+            Some(ref clock) => clock.0.sleep(duration),
+            None => thread::sleep(duration),
+        }
+    }
+
+    fn refilled(&self, len: usize) {
+        if let Some(ref hook) = self.on_refill {
+            hook.with(|f| f(len));
+        }
+    }
+}
+
+/// Source of time for the wrapper, used to measure durations and to wait
+/// between retries.
+///
+/// The default clock is [`SystemClock`]. Custom clocks allow testing code
+/// relying on backoffs, deadlines and other durations without waiting.
+pub trait Clock: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+
+    /// Blocks the current thread for `duration`.
+    fn sleep(&self, duration: Duration);
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+}
+
+/// Clock of the system, based on `Instant::now` and `thread::sleep`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+#[derive(Clone)]
+struct SharedClock(Arc<dyn Clock>);
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Clock")
+    }
+}
+
+/// Kind of operation an IO object is waited on for.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Interest {
+    /// Waiting until reading would not block.
+    Read,
+    /// Waiting until writing would not block.
+    Write,
+}
+
+/// How to wait for the inner value to be ready after `WouldBlock`.
+#[derive(Clone, Debug)]
+enum Waiter<T> {
+    Custom(WaitHook),
+    #[cfg(unix)]
+    Poll(fn(&T, Interest, Option<Duration>) -> io::Result<()>),
+}
+
+impl<T> Waiter<T> {
+    fn wait(&self, inner: &T, interest: Interest, timeout: Option<Duration>)
+        -> io::Result<()>
+    {
+        match *self {
+            Waiter::Custom(ref wait) => wait.with(|f| f(interest)),
+            #[cfg(unix)]
+            Waiter::Poll(wait) => wait(inner, interest, timeout),
+        }
+    }
+}
+
+/// Counters maintained over the lifetime of a wrapper.
+#[cfg(feature = "stats")]
+#[derive(Clone, Debug, Default)]
+struct Stats {
+    operations: u64,
+    retries: u64,
+    bytes_read: u64,
+    bytes_written: u64,
+}
+
+/// Copy of an error, which can be cloned in turn.
+#[derive(Debug)]
+struct ErrorCopy(io::Error);
+
+impl ErrorCopy {
+    fn new(e: &io::Error) -> Self {
+        ErrorCopy(match e.raw_os_error() {
+            Some(code) => io::Error::from_raw_os_error(code),
+            None => io::Error::new(e.kind(), e.to_string()),
+        })
+    }
+}
+
+impl Clone for ErrorCopy {
+    fn clone(&self) -> Self {
+        ErrorCopy::new(&self.0)
+    }
+}
+
+/// Bytes read over the sliding window of the minimum throughput.
+#[derive(Clone, Debug, Default)]
+struct Throughput {
+    start: Option<Instant>,
+    samples: VecDeque<(Instant, u64)>,
+    total: u64,
+}
+
+/// Series of identical retries being reported.
+#[derive(Clone, Debug, Default)]
+struct RetryEvents {
+    last: Option<(ErrorKind, RetryReason)>,
+    streak: u32,
+    suppressed: u32,
+    pending: Option<u32>,
+    last_report: Option<Instant>,
+}
+
+/// How to seek back to where a failed read started.
+#[derive(Clone, Debug)]
+struct Rewind<T> {
+    kind: ErrorKind,
+    position: fn(&mut T) -> io::Result<u64>,
+    seek: fn(&mut T, u64) -> io::Result<u64>,
+}
+
+/// Callback shared by the clones of a wrapper.
+struct Hook<F: ?Sized>(Arc<Mutex<F>>);
+
+impl<F: ?Sized> Hook<F> {
+    fn with<R>(&self, call: impl FnOnce(&mut F) -> R) -> R {
+        call(&mut self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl<F: ?Sized> Clone for Hook<F> {
+    fn clone(&self) -> Self {
+        Hook(self.0.clone())
+    }
+}
+
+impl<F: ?Sized> fmt::Debug for Hook<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Hook")
+    }
+}
+
+const CHUNK_SIZE: usize = 8 * 1024;
+const MAX_ZERO_WRITES: u32 = 3;
+const DEFAULT_YIELD_INTERVAL: u32 = 8;
+
+fn copy_init(dst: &mut [MaybeUninit<u8>], src: &[u8]) {
+    for (d, &s) in dst.iter_mut().zip(src) {
+        *d = MaybeUninit::new(s);
+    }
+}
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(ErrorKind::InvalidData,
+        "stream did not contain valid UTF-8")
+}
+
+impl<T: Read, P: RetryPolicy> Retry<T, P> {
+    fn refill_read_buffer(&mut self, capacity: usize) -> io::Result<()> {
+        // The buffer is taken out of the wrapper so that the inner reader can
+        // read into it directly, and its allocation is reused across refills.
+        let mut staged = mem::take(&mut self.staged);
+        staged.resize(capacity, 0);
+        let res = self.retry_read(|inner| inner.read(staged.make_contiguous()));
+        staged.truncate(*res.as_ref().unwrap_or(&0));
+        self.staged = staged;
+        res.map(drop)
+    }
+
+    fn read_exact_retrying_eof(&mut self, mut buf: &mut [u8])
+        -> io::Result<()>
+    {
+        let previous = self.deadline;
+        let max = self.config.settings.retry_eof.map_or(0, |(max, _)| max);
+        let mut eofs = 0;
+        let mut slept = Duration::from_secs(0);
+        while !buf.is_empty() {
+            let failing_since = self.failing_since;
+            match self.read(buf)? {
+                0 => {
+                    let e = io::Error::new(ErrorKind::UnexpectedEof,
+                        "failed to fill whole buffer");
+                    if eofs == max {
+                        return Err(e);
+                    }
+                    if eofs == 0 {
+                        // Retries of the end of the stream are retries of a
+                        // read, bounded by the read deadline.
+                        self.start_read_deadline();
+                    }
+                    // The read succeeded, which reset the start of the
+                    // failures used to escalate.
+                    self.failing_since = failing_since;
+                    if !self.retry_empty_read(&e, RetryReason::Eof, eofs,
+                        &mut slept)?
+                    {
+                        return Err(e);
+                    }
+                    eofs += 1;
+                }
+                n => {
+                    buf = &mut buf[n..];
+                    eofs = 0;
+                    slept = Duration::from_secs(0);
+                    self.deadline = previous;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: BufRead, P: RetryPolicy> Retry<T, P> {
+    // Returns whether the inner buffer is still empty once the empty fills
+    // are no longer retried.
+    fn fill_retrying_empty(&mut self) -> io::Result<bool> {
+        // Retries of empty fills are retries of a read, bounded by the read
+        // deadline.
+        self.start_read_deadline();
+        let mut empty_fills = 0;
+        let mut slept = Duration::from_secs(0);
+        let is_empty = |inner: &mut T| inner.fill_buf().map(|b| b.is_empty());
+        let mut failing_since = self.failing_since;
+        while self.retry_read(is_empty)? {
+            // The fill succeeded, which reset the start of the failures used
+            // to escalate.
+            self.failing_since = failing_since;
+            let e = io::Error::from(ErrorKind::UnexpectedEof);
+            if empty_fills == self.config.settings.empty_fill_retries
+                || !self.retry_empty_read(&e, RetryReason::EmptyFill,
+                    empty_fills, &mut slept)?
+            {
+                return Ok(true);
+            }
+            failing_since = self.failing_since;
+            empty_fills += 1;
+        }
+        Ok(false)
+    }
+}
+
+impl<T: Read, P: RetryPolicy> Read for Retry<T, P> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.count_operation();
+        let buffered = match self.config.settings.read_buffer {
+            Some(capacity)
+                if self.staged.is_empty() && buf.len() < capacity =>
+            {
+                self.refill_read_buffer(capacity)?;
+                true
+            }
+            _ => !self.staged.is_empty(),
+        };
+        let n = if !buffered {
+            self.retry_read(|inner| inner.read(buf))?
+        } else {
+            let n = (&*self.staged.make_contiguous()).read(buf)?;
+            self.staged.drain(..n);
+            n
+        };
+        self.available = self.available.saturating_sub(n);
+        self.advance_read(n);
+        Ok(n)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>])
+        -> io::Result<usize>
+    {
+        if !self.staged.is_empty() || self.config.settings.read_buffer.is_some()
+        {
+            let buf = bufs.iter_mut().find(|buf| !buf.is_empty());
+            return self.read(buf.map_or(&mut [][..], |buf| &mut **buf));
+        }
+        self.count_operation();
+        let n = self.retry_read(|inner| inner.read_vectored(bufs))?;
+        self.available = self.available.saturating_sub(n);
+        self.advance_read(n);
+        Ok(n)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let start = buf.len();
+        let mut chunk = [0u8; CHUNK_SIZE];
+        loop {
+            match self.read(&mut chunk)? {
+                0 => return Ok(buf.len() - start),
+                n => buf.extend_from_slice(&chunk[..n]),
+            }
+        }
+    }
+
+    fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
+        let mut bytes = Vec::new();
+        let res = self.read_to_end(&mut bytes);
+        match String::from_utf8(bytes) {
+            Ok(s) => {
+                buf.push_str(&s);
+                res
+            }
+            Err(_) => res.and_then(|_| Err(invalid_utf8())),
+        }
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        let previous = self.deadline;
+        let res = self.read_exact_retrying_eof(buf);
+        self.deadline = previous;
+        res
+    }
+}
+
+impl<T: BufRead, P: RetryPolicy> BufRead for Retry<T, P> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.count_operation();
+        if !self.staged.is_empty() {
+            self.available = self.staged.len();
+            return Ok(self.staged.make_contiguous());
+        }
+        let refill = self.available == 0;
+        let previous = self.deadline;
+        let empty = self.fill_retrying_empty();
+        self.deadline = previous;
+        if empty? {
+            self.available = 0;
+            if refill {
+                self.config.settings.refilled(0);
+            }
+            return Ok(&[]);
+        }
+        // The buffer of the inner reader is not empty, so per the contract of
+        // `BufRead` this returns it without performing IO again.
+        let buf = self.inner.fill_buf()?;
+        self.available = buf.len();
+        if refill {
+            self.config.settings.refilled(buf.len());
+        }
+        Ok(buf)
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.available = self.available.saturating_sub(n);
+        self.advance_read(n);
+        if self.staged.is_empty() {
+            self.inner.consume(n)
+        } else {
+            self.staged.drain(..n.min(self.staged.len()));
+        }
+    }
+
+    fn read_until(&mut self, byte: u8, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let start = buf.len();
+        loop {
+            let (done, used) = {
+                let available = self.fill_buf()?;
+                match available.iter().position(|&b| b == byte) {
+                    Some(i) => {
+                        buf.extend_from_slice(&available[..=i]);
+                        (true, i + 1)
+                    }
+                    None => {
+                        buf.extend_from_slice(available);
+                        (available.is_empty(), available.len())
+                    }
+                }
+            };
+            self.consume(used);
+            if done {
+                return Ok(buf.len() - start);
+            }
+        }
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        let mut bytes = Vec::new();
+        let res = self.read_until(b'\n', &mut bytes);
+        match String::from_utf8(bytes) {
+            Ok(s) => {
+                buf.push_str(&s);
+                res
+            }
+            Err(_) => res.and_then(|_| Err(invalid_utf8())),
+        }
+    }
+}
+
+impl<T: Write, P: RetryPolicy> Write for Retry<T, P> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.count_operation();
+        let n = match self.write_coalesced(buf) {
+            Err(ref e) if self.ignores_broken_pipe(e) => {
+                self.broken_pipe();
+                buf.len()
+            }
+            res => res?,
+        };
+        self.advance_written(n);
+        // The bytes were accepted, so a failed flush is left for the next
+        // write to retry and report.
+        let _ = self.flush_if_due();
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.config.settings.skip_redundant_flush && !self.dirty {
+            return Ok(());
+        }
+        let res = self.write_pending()
+            .and_then(|_| self.retry_write(|inner| inner.flush()));
+        match res {
+            Err(ref e) if self.ignores_broken_pipe(e) => self.broken_pipe(),
+            res => res?,
+        }
+        self.dirty = false;
+        self.unflushed = 0;
+        Ok(())
+    }
+
+    fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf)? {
+                0 => return Err(io::Error::new(ErrorKind::WriteZero,
+                    "failed to write whole buffer")),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Text is forwarded as is. `fmt::Error` carries no information about what
+/// went wrong, so there is nothing a policy could decide on and nothing is
+/// retried.
+impl<T: fmt::Write, P> fmt::Write for Retry<T, P> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_str(s)
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        self.inner.write_char(c)
+    }
+}
+
+/// Registering the wrapper with a `mio` registry registers the inner value.
+///
+/// Errors of kind `WouldBlock` are returned unchanged by the default policy,
+/// so that the event loop can wait for the next readiness event, while
+/// `Interrupted` keeps being retried. This requires the `mio` feature.
+#[cfg(feature = "mio")]
+impl<T: mio::event::Source, P> mio::event::Source for Retry<T, P> {
+    fn register(&mut self, registry: &mio::Registry, token: mio::Token,
+        interests: mio::Interest) -> io::Result<()>
+    {
+        self.inner.register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &mio::Registry, token: mio::Token,
+        interests: mio::Interest) -> io::Result<()>
+    {
+        self.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        self.inner.deregister(registry)
+    }
+}
+
+/// Seeking is retried like reading. Relative seeks are first resolved to an
+/// absolute position so that retrying a seek that was interrupted after the
+/// inner value had already moved does not move it twice.
+impl<T: Seek, P: RetryPolicy> Seek for Retry<T, P> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.write_pending_before_seek()?;
+        if pos == SeekFrom::Current(0) {
+            return self.stream_position();
+        }
+        let target = match pos {
+            SeekFrom::Start(n) => n,
+            SeekFrom::Current(offset) => {
+                let current = self.stream_position()?;
+                offset_position(current, offset)?
+            }
+            SeekFrom::End(offset) => {
+                self.discard_buffered();
+                let end = self.retry(|inner| inner.seek(SeekFrom::End(0)))?;
+                if offset == 0 {
+                    self.position = end;
+                    return Ok(end);
+                }
+                offset_position(end, offset)?
+            }
+        };
+        self.discard_buffered();
+        self.position = self.retry(|inner| {
+            inner.seek(SeekFrom::Start(target))
+        })?;
+        Ok(self.position)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        self.write_pending_before_seek()?;
+        let pos = self.retry(|inner| inner.stream_position())?;
+        self.position = pos.saturating_sub(self.staged.len() as u64);
+        Ok(self.position)
+    }
+}
+
+impl<T, P> Retry<T, P> {
+    fn write_pending_before_seek(&mut self) -> io::Result<()> {
+        match self.write_before_seek {
+            Some(write_pending) if !self.pending.is_empty() =>
+                write_pending(self),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn offset_position(base: u64, offset: i64) -> io::Result<u64> {
+    let pos = if offset >= 0 {
+        base.checked_add(offset as u64)
+    } else {
+        base.checked_sub(offset.unsigned_abs())
+    };
+    pos.ok_or_else(|| io::Error::new(ErrorKind::InvalidInput,
+        "invalid seek to a negative or overflowing position"))
+}
+
+#[cfg(test)]
+mod tests {
+    use partial_io::{PartialOp, PartialRead, PartialWrite};
+    use std::io::{BufRead, BufReader, BufWriter, Cursor, ErrorKind, Read};
+    use std::io::{Seek, SeekFrom, self, Write};
+    use std::mem::{self, MaybeUninit};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use crate::policy::{self, RetryReason};
+    use super::{Interest, pipe, retry_connect, retry_results, Retry};
+    use super::{CHUNK_SIZE, RetryBuilder};
+    use super::{Escalate, RetryEvent, RetryEventCoalescing, RetryOverrides};
+    use super::{Backoff, Clock, RetryConfig, RetryProfile};
+    use super::{Operation, Recording, WriteReport};
+
+    struct ErrorsFirst<T> {
+        errors: Vec<io::Error>,
+        inner: T,
+    }
+
+    impl<T: Read> Read for ErrorsFirst<T> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.errors.is_empty() {
+                self.inner.read(buf)
+            } else {
+                Err(self.errors.remove(0))
+            }
+        }
+
+        fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>])
+            -> io::Result<usize>
+        {
+            if self.errors.is_empty() {
+                self.inner.read_vectored(bufs)
+            } else {
+                Err(self.errors.remove(0))
+            }
+        }
+    }
+
+    struct InterruptedSeeks<T> {
+        inner: T,
+        interrupts: u32,
+    }
+
+    impl<T: Read> Read for InterruptedSeeks<T> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl<T: Seek> Seek for InterruptedSeeks<T> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            if self.interrupts > 0 {
+                self.interrupts -= 1;
+                return Err(ErrorKind::Interrupted.into());
+            }
+            self.inner.seek(pos)
+        }
+    }
+
+    struct InterruptedAfterSeeking<T> {
+        inner: T,
+        interrupt: bool,
+    }
+
+    impl<T: Seek> Seek for InterruptedAfterSeeking<T> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            let res = self.inner.seek(pos);
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                Err(ErrorKind::Interrupted.into())
+            } else {
+                res
+            }
+        }
+    }
+
+    struct Duplex {
+        reader: PartialRead<&'static [u8]>,
+        writer: PartialWrite<Vec<u8>>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reader.read(buf)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writer.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.writer.flush()
+        }
+    }
+
+    #[test]
+    fn reads() {
+        let input = &b"Read test"[..];
+        let ops = vec![PartialOp::Err(ErrorKind::Interrupted)];
+        let mut reader = Retry::new(PartialRead::new(input, ops));
+        let mut out = vec![0u8; input.len()];
+        assert_eq!(reader.read(&mut out).unwrap(), input.len());
+        assert_eq!(&out[..], input);
+    }
+
+    #[test]
+    fn reads_buffered() {
+        let input = &b"Read test"[..];
+        let ops = vec![PartialOp::Err(ErrorKind::Interrupted)];
+        let mut reader = Retry::new(BufReader::with_capacity(input.len(),
+            PartialRead::new(input, ops)));
+        assert_eq!(reader.fill_buf().unwrap(), input);
+    }
+
+    #[test]
+    fn writes() {
+        let input = &b"Write test"[..];
+        let ops = vec![PartialOp::Err(ErrorKind::Interrupted)];
+        let mut writer = Retry::new(PartialWrite::new(Vec::<u8>::new(), ops));
+        assert_eq!(writer.write(input).unwrap(), input.len());
+        assert_eq!(&writer.into_inner().into_inner()[..], input);
+    }
+
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    #[test]
+    fn is_send_and_sync_with_hooks() {
+        let reader = RetryBuilder::new()
+            .on_give_up(|_, _| {})
+            .on_retry(|_| {})
+            .build(Cursor::new(b"Threaded".to_vec()))
+            .reconnect_on(ErrorKind::ConnectionReset, || {
+                Ok(Cursor::new(Vec::new()))
+            });
+        assert_send_sync(&reader);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        assert_send_sync(&Retry::new(listener));
+        let out = thread::spawn(move || {
+            let mut reader = reader;
+            let mut out = String::new();
+            reader.read_to_string(&mut out).map(|_| out)
+        });
+        assert_eq!(out.join().unwrap().unwrap(), "Threaded");
+    }
+
+    #[test]
+    fn profiles_differ_on_timed_out() {
+        let input = &b"Profile test"[..];
+        let ops = || vec![PartialOp::Err(ErrorKind::TimedOut)];
+        let mut out = vec![0u8; input.len()];
+        let mut reader = Retry::with_profile(PartialRead::new(input, ops()),
+            RetryProfile::Aggressive);
+        assert_eq!(reader.read(&mut out).unwrap(), input.len());
+        let mut reader = Retry::with_profile(PartialRead::new(input, ops()),
+            RetryProfile::Conservative);
+        assert_eq!(reader.read(&mut out).unwrap_err().kind(),
+            ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn none_profile_surfaces_interrupted_from_bulk_reads() {
+        let input = &b"Bulk test"[..];
+        let ops = vec![PartialOp::Err(ErrorKind::Interrupted)];
+        let mut reader = Retry::with_profile(PartialRead::new(input, ops),
+            RetryProfile::None);
+        let mut out = Vec::new();
+        assert_eq!(reader.read_to_end(&mut out).unwrap_err().kind(),
+            ErrorKind::Interrupted);
+    }
+
+    #[test]
+    fn skips_by_reading() {
+        let input = &b"Skip test"[..];
+        let ops = vec![
+            PartialOp::Limited(2),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(3),
+        ];
+        let mut reader = Retry::new(PartialRead::new(input, ops));
+        assert_eq!(reader.skip(5).unwrap(), 5);
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "test");
+        assert_eq!(reader.skip(5).unwrap(), 0);
+    }
+
+    #[test]
+    fn skips_buffered_bytes_first() {
+        let input = &b"Skip test"[..];
+        let mut reader = Retry::new(BufReader::with_capacity(4, input));
+        assert_eq!(reader.fill_buf().unwrap(), b"Skip");
+        assert_eq!(reader.skip(6).unwrap(), 6);
+        assert_eq!(reader.fill_buf().unwrap(), b"es");
+    }
+
+    #[test]
+    fn skips_by_seeking() {
+        let inner = InterruptedSeeks {
+            inner: Cursor::new(b"Skip test".to_vec()),
+            interrupts: 2,
+        };
+        let mut reader = Retry::new(inner);
+        assert_eq!(reader.skip_by_seek(5).unwrap(), 5);
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "test");
+        reader.seek(SeekFrom::Start(7)).unwrap();
+        assert_eq!(reader.skip_by_seek(5).unwrap(), 2);
+    }
+
+    #[test]
+    fn reports_fragmented_writes() {
+        let input = &b"Fragmented write test"[..];
+        let ops = vec![
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ].into_iter().cycle();
+        let mut writer = Retry::new(PartialWrite::new(Vec::<u8>::new(), ops));
+        let report = writer.write_all_reporting(input).unwrap();
+        assert_eq!(report, WriteReport {
+            total_written: input.len(),
+            inner_calls: 13,
+            retries: 6,
+        });
+        assert_eq!(&writer.into_inner().into_inner()[..], input);
+        let mut writer = RetryBuilder::new()
+            .coalesce(8)
+            .flush_every(6)
+            .build(CountingWriter::default());
+        assert_eq!(writer.write_all_reporting(b"abc").unwrap(), WriteReport {
+            total_written: 3,
+            inner_calls: 0,
+            retries: 0,
+        });
+        assert_eq!(writer.write_all_reporting(b"defg").unwrap(), WriteReport {
+            total_written: 4,
+            inner_calls: 1,
+            retries: 0,
+        });
+        let inner = writer.into_inner();
+        assert_eq!(inner.written, b"abcdefg");
+        assert_eq!(inner.flushes, 1);
+        let mut writer = RetryBuilder::new()
+            .ignore_broken_pipe(true)
+            .build(BrokenPipe);
+        assert_eq!(writer.write_all_reporting(b"lost").unwrap().total_written,
+            4);
+    }
+
+    #[test]
+    fn reports_write_zero() {
+        let mut sink = [0u8; 2];
+        let mut writer = Retry::new(&mut sink[..]);
+        assert_eq!(writer.write_all_reporting(b"abc").unwrap_err().kind(),
+            ErrorKind::WriteZero);
+    }
+
+    #[test]
+    fn calls_give_up_hook_once_on_failure() {
+        let input = &b"Give up test"[..];
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(4),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut reader = RetryBuilder::new()
+            .max_retries(2)
+            .on_give_up({
+                let calls = calls.clone();
+                move |e, attempts| {
+                    calls.lock().unwrap().push((e.kind(), attempts))
+                }
+            })
+            .build(PartialRead::new(input, ops));
+        let mut out = [0u8; 4];
+        assert_eq!(reader.read(&mut out).unwrap(), 4);
+        assert!(calls.lock().unwrap().is_empty());
+        assert_eq!(reader.read(&mut out).unwrap_err().kind(),
+            ErrorKind::Interrupted);
+        assert_eq!(*calls.lock().unwrap(), [(ErrorKind::Interrupted, 3)]);
+        assert_eq!(reader.read(&mut out).unwrap(), 4);
+        assert_eq!(calls.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn shares_total_budget_between_reads_and_writes() {
+        let interrupts = || vec![PartialOp::Err(ErrorKind::Interrupted); 2]
+            .into_iter()
+            .chain(vec![PartialOp::Unlimited; 2])
+            .cycle();
+        let mut duplex = Retry::with_total_budget(Duplex {
+            reader: PartialRead::new(&b"abcdef"[..], interrupts()),
+            writer: PartialWrite::new(Vec::new(), interrupts()),
+        }, 3);
+        let mut out = [0u8; 2];
+        assert_eq!(duplex.read(&mut out).unwrap(), 2);
+        assert_eq!(duplex.remaining_budget(), Some(1));
+        assert_eq!(duplex.write(b"ab").unwrap_err().kind(),
+            ErrorKind::Interrupted);
+        assert_eq!(duplex.remaining_budget(), Some(0));
+        duplex.refill_budget(1);
+        assert_eq!(duplex.write(b"ab").unwrap(), 2);
+        assert_eq!(duplex.read(&mut out).unwrap(), 2);
+        assert_eq!(duplex.remaining_budget(), Some(1));
+        assert_eq!(duplex.read(&mut out).unwrap_err().kind(),
+            ErrorKind::Interrupted);
+        assert_eq!(duplex.remaining_budget(), Some(0));
+    }
+
+    #[test]
+    fn retries_relative_seeks_idempotently() {
+        let mut inner = Cursor::new(vec![0u8; 20]);
+        inner.set_position(2);
+        let mut seeker = Retry::new(InterruptedAfterSeeking {
+            inner,
+            interrupt: false,
+        });
+        assert_eq!(seeker.seek(SeekFrom::Current(5)).unwrap(), 7);
+        assert_eq!(seeker.seek(SeekFrom::Current(-3)).unwrap(), 4);
+        assert_eq!(seeker.seek(SeekFrom::End(-2)).unwrap(), 18);
+        assert_eq!(seeker.seek(SeekFrom::Start(1)).unwrap(), 1);
+        assert_eq!(seeker.seek(SeekFrom::Current(-2)).unwrap_err().kind(),
+            ErrorKind::InvalidInput);
+        assert_eq!(seeker.into_inner().inner.position(), 1);
+    }
+
+    #[test]
+    fn takes_written_buffer() {
+        let mut writer = Retry::new(Vec::new());
+        write!(writer, "first {}", 1).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.take_buffer(), b"first 1");
+        writer.write_all(b"second").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.take_buffer(), b"second");
+        assert!(writer.take_buffer().is_empty());
+    }
+
+    #[test]
+    fn flushes_stuck_buffer() {
+        let ops = vec![
+            PartialOp::Limited(2),
+            PartialOp::Err(ErrorKind::TimedOut),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ].into_iter().cycle();
+        let inner = BufWriter::with_capacity(16,
+            PartialWrite::new(Vec::new(), ops));
+        let mut writer = RetryBuilder::new()
+            .retry_on(ErrorKind::TimedOut)
+            .build(inner);
+        writer.write_all(b"Flush test").unwrap();
+        writer.flush_buffer_with_retry().unwrap();
+        let inner = writer.into_inner();
+        assert!(inner.buffer().is_empty());
+        assert_eq!(inner.get_ref().get_ref(), b"Flush test");
+    }
+
+    fn recording_reasons<P>(builder: RetryBuilder<P>)
+        -> (RetryBuilder<P>, Arc<Mutex<Vec<RetryReason>>>)
+    {
+        let reasons = Arc::new(Mutex::new(Vec::new()));
+        let builder = builder.on_retry({
+            let reasons = reasons.clone();
+            move |info| reasons.lock().unwrap().push(info.reason())
+        });
+        (builder, reasons)
+    }
+
+    #[test]
+    fn classifies_retries() {
+        let failing = |error| ErrorsFirst {
+            errors: vec![error],
+            inner: &b"reason"[..],
+        };
+        let mut out = [0u8; 6];
+
+        let (builder, reasons) = recording_reasons(RetryBuilder::new());
+        let mut reader = builder.build(failing(ErrorKind::Interrupted.into()));
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(*reasons.lock().unwrap(), [RetryReason::Interrupted]);
+
+        let (builder, reasons) = recording_reasons(RetryBuilder::new()
+            .retry_on(ErrorKind::TimedOut));
+        let mut reader = builder.build(failing(ErrorKind::TimedOut.into()));
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(*reasons.lock().unwrap(), [RetryReason::Kind]);
+
+        let (builder, reasons) = recording_reasons(RetryBuilder::new()
+            .retry_on_os_error(9999));
+        let mut reader = builder
+            .build(failing(io::Error::from_raw_os_error(9999)));
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(*reasons.lock().unwrap(), [RetryReason::OsError]);
+
+        let (builder, reasons) = recording_reasons(RetryBuilder::new()
+            .policy(policy::from_fn(|e, _| e.kind() == ErrorKind::Other)));
+        let mut reader = builder.build(failing(ErrorKind::Other.into()));
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(*reasons.lock().unwrap(), [RetryReason::Predicate]);
+
+        let ops = vec![PartialOp::Limited(0), PartialOp::Limited(0)];
+        let (builder, reasons) = recording_reasons(RetryBuilder::new()
+            .retry_empty_fill(3));
+        let mut reader = builder
+            .build(BufReader::new(PartialRead::new(&b"reason"[..], ops)));
+        assert_eq!(reader.fill_buf().unwrap(), b"reason");
+        assert_eq!(*reasons.lock().unwrap(),
+            [RetryReason::EmptyFill, RetryReason::EmptyFill]);
+    }
+
+    #[test]
+    fn limits_empty_fill_retries() {
+        let ops = vec![PartialOp::Limited(0); 3];
+        let mut reader = RetryBuilder::new()
+            .retry_empty_fill(2)
+            .build(BufReader::new(PartialRead::new(&b"eof"[..], ops)));
+        assert!(reader.fill_buf().unwrap().is_empty());
+        assert_eq!(reader.fill_buf().unwrap(), b"eof");
+    }
+
+    #[test]
+    fn limits_empty_fill_retries_in_time() {
+        let empty_fills = |builder: RetryBuilder| {
+            let clock = ManualClock::new();
+            let mut reader = builder
+                .retry_empty_fill(100)
+                .backoff(Backoff::Constant(Duration::from_millis(10)))
+                .clock(clock.clone())
+                .build(BufReader::new(&b""[..]));
+            assert!(reader.fill_buf().unwrap().is_empty());
+            let elapsed = *clock.elapsed.lock().unwrap();
+            elapsed
+        };
+        assert_eq!(empty_fills(RetryBuilder::new()
+            .read_deadline(Duration::from_millis(25))),
+            Duration::from_millis(25));
+        assert_eq!(empty_fills(RetryBuilder::new()
+            .max_backoff_total(Duration::from_millis(15))),
+            Duration::from_millis(10));
+        assert_eq!(empty_fills(RetryBuilder::new()
+            .escalate_after(Duration::from_millis(15), || Escalate::Fail)),
+            Duration::from_millis(20));
+    }
+
+    #[test]
+    fn retries_eof_in_read_exact() {
+        let ops = || vec![
+            PartialOp::Limited(3),
+            PartialOp::Limited(0),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(0),
+            PartialOp::Limited(2),
+            PartialOp::Limited(0),
+        ];
+        let reader = |builder: RetryBuilder| {
+            builder.build(PartialRead::new(&b"growing"[..], ops()))
+        };
+        let mut buf = [0u8; 7];
+        let e = reader(RetryBuilder::new()).read_exact(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+        let backoff = Backoff::Constant(Duration::from_millis(1));
+        let reasons = Arc::new(Mutex::new(Vec::new()));
+        let mut tail = {
+            let reasons = reasons.clone();
+            reader(RetryBuilder::new()
+                .retry_eof(2, backoff)
+                .on_retry(move |info| {
+                    reasons.lock().unwrap().push(info.reason())
+                }))
+        };
+        tail.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"growing");
+        assert_eq!(*reasons.lock().unwrap(), [
+            RetryReason::Eof,
+            RetryReason::Interrupted,
+            RetryReason::Eof,
+            RetryReason::Eof,
+        ]);
+        let e = tail.read_exact(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn limits_eof_retries() {
+        let eofs = |builder: RetryBuilder| {
+            let clock = ManualClock::new();
+            let mut reader = builder
+                .retry_eof(100, Backoff::Constant(Duration::from_millis(10)))
+                .clock(clock.clone())
+                .build(&b"ab"[..]);
+            let e = reader.read_exact(&mut [0u8; 4]).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+            let elapsed = *clock.elapsed.lock().unwrap();
+            elapsed
+        };
+        assert_eq!(eofs(RetryBuilder::new()
+            .read_deadline(Duration::from_millis(25))),
+            Duration::from_millis(25));
+        assert_eq!(eofs(RetryBuilder::new()
+            .max_backoff_total(Duration::from_millis(15))),
+            Duration::from_millis(10));
+        assert_eq!(eofs(RetryBuilder::new()
+            .escalate_after(Duration::from_millis(15), || Escalate::Fail)),
+            Duration::from_millis(20));
+        assert_eq!(eofs(RetryBuilder::new().total_budget(3)),
+            Duration::from_millis(30));
+    }
+
+    #[test]
+    fn reads_into_uninitialized_buffer() {
+        let input = &b"Uninit test"[..];
+        let ops = vec![PartialOp::Err(ErrorKind::Interrupted)];
+        let mut reader = Retry::new(PartialRead::new(input, ops));
+        let mut buf = [MaybeUninit::<u8>::uninit(); 16];
+        let n = reader.read_uninit(&mut buf).unwrap();
+        assert_eq!(n, input.len());
+        let read = buf[..n].iter()
+            .map(|b| unsafe { b.assume_init() })
+            .collect::<Vec<_>>();
+        assert_eq!(read, input);
+        let input = vec![7u8; 3 * CHUNK_SIZE];
+        let mut reader = Retry::new(&input[..]);
+        let mut buf = vec![MaybeUninit::<u8>::uninit(); input.len()];
+        assert_eq!(reader.read_uninit(&mut buf).unwrap(), input.len());
+        let mut reader = Retry::new(&b"ab"[..]);
+        let mut buf = [MaybeUninit::new(9u8); 4];
+        assert_eq!(reader.read_uninit(&mut buf).unwrap(), 2);
+        let read = buf.iter()
+            .map(|b| unsafe { b.assume_init() })
+            .collect::<Vec<_>>();
+        assert_eq!(read, b"ab\x09\x09");
+    }
+
+    #[test]
+    fn tracks_available_bytes() {
+        let input = &b"Available test"[..];
+        let ops = vec![PartialOp::Err(ErrorKind::Interrupted)];
+        let mut reader = Retry::new(BufReader::with_capacity(9,
+            PartialRead::new(input, ops)));
+        assert_eq!(reader.available(), 0);
+        assert_eq!(reader.fill_buf().unwrap(), b"Available");
+        assert_eq!(reader.available(), 9);
+        reader.consume(5);
+        assert_eq!(reader.available(), 4);
+        let mut out = [0u8; 3];
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(reader.available(), 1);
+        assert_eq!(reader.fill_buf().unwrap(), b"e");
+        reader.consume(1);
+        assert_eq!(reader.available(), 0);
+        assert_eq!(reader.fill_buf().unwrap(), b" test");
+        assert_eq!(reader.available(), 5);
+    }
+
+    #[test]
+    fn reconnects_after_reset() {
+        let ops = vec![
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::ConnectionReset),
+        ];
+        let mut connections = vec![&b"def"[..]].into_iter();
+        let mut reader = Retry::new(PartialRead::new(&b"abc"[..], ops))
+            .reconnect_on(ErrorKind::ConnectionReset, move || {
+                connections.next()
+                    .map(|data| PartialRead::new(data, vec![]))
+                    .ok_or_else(|| ErrorKind::ConnectionRefused.into())
+            });
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "abcdef");
+    }
+
+    #[test]
+    fn limits_reconnects_to_retry_limit() {
+        let reset = || {
+            PartialRead::new(&b""[..], vec![
+                PartialOp::Err(ErrorKind::ConnectionReset),
+            ])
+        };
+        let reconnects = Arc::new(Mutex::new(0));
+        let mut reader = RetryBuilder::new()
+            .max_retries(3)
+            .build(reset())
+            .reconnect_on(ErrorKind::ConnectionReset, {
+                let reconnects = reconnects.clone();
+                move || {
+                    *reconnects.lock().unwrap() += 1;
+                    Ok(reset())
+                }
+            });
+        let e = reader.read(&mut [0u8; 4]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::ConnectionReset);
+        assert_eq!(*reconnects.lock().unwrap(), 3);
+    }
+
+    #[test]
+    fn fills_to_capacity() {
+        let ops = vec![
+            PartialOp::Limited(2),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(4),
+        ];
+        let source = PartialRead::new(&b"Fill to capacity"[..], ops);
+        let mut reader = Retry::new(BufReader::with_capacity(6, source));
+        assert_eq!(reader.fill_to_capacity().unwrap(), b"Fill t");
+        assert_eq!(reader.available(), 6);
+        reader.consume(2);
+        assert_eq!(reader.fill_buf().unwrap(), b"ll t");
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "ll to capacity");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn polls_when_would_block() {
+        use std::os::unix::net::UnixStream;
+
+        let (reader, mut writer) = UnixStream::pair().unwrap();
+        reader.set_nonblocking(true).unwrap();
+        let mut reader = Retry::new(reader).poll_would_block(true);
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            writer.write_all(b"ready").unwrap();
+        });
+        let mut out = [0u8; 5];
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(&out, b"ready");
+        sender.join().unwrap();
+        let (reader, _writer) = UnixStream::pair().unwrap();
+        reader.set_nonblocking(true).unwrap();
+        let mut reader = RetryBuilder::new()
+            .read_deadline(Duration::from_millis(20))
+            .build(reader)
+            .poll_would_block(true);
+        let e = reader.read_within(&mut out, Duration::from_secs(60))
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+    }
+
+    struct NotReady<T> {
+        inner: T,
+        ready: Arc<Mutex<bool>>,
+    }
+
+    impl<T: Write> Write for NotReady<T> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut ready = self.ready.lock().unwrap();
+            if !*ready {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            *ready = false;
+            self.inner.write(&buf[..1])
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    #[test]
+    fn waits_for_readiness() {
+        let ready = Arc::new(Mutex::new(false));
+        let waits = Arc::new(Mutex::new(Vec::new()));
+        let mut writer = Retry::with_waiter(NotReady {
+            inner: Vec::new(),
+            ready: ready.clone(),
+        }, {
+            let waits = waits.clone();
+            move |interest| {
+                waits.lock().unwrap().push(interest);
+                *ready.lock().unwrap() = true;
+                Ok(())
+            }
+        });
+        writer.write_all(b"abc").unwrap();
+        assert_eq!(writer.into_inner().inner, b"abc");
+        assert_eq!(*waits.lock().unwrap(), vec![Interest::Write; 3]);
+    }
+
+    #[test]
+    fn returns_waiter_errors() {
+        let mut writer = Retry::with_waiter(NotReady {
+            inner: Vec::new(),
+            ready: Arc::new(Mutex::new(false)),
+        }, |_| Err(ErrorKind::BrokenPipe.into()));
+        assert_eq!(writer.write(b"abc").unwrap_err().kind(),
+            ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn counts_buffered_bytes() {
+        let ops = vec![PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(5)];
+        let source = PartialRead::new(&b"Buffered"[..], ops);
+        let mut reader = Retry::new(BufReader::new(source));
+        assert_eq!(reader.buffered_len(), 0);
+        reader.fill_buf().unwrap();
+        assert_eq!(reader.buffered_len(), 5);
+        reader.consume(2);
+        assert_eq!(reader.buffered_len(), 3);
+        reader.fill_to_capacity().unwrap();
+        assert_eq!(reader.buffered_len(), 6);
+    }
+
+    #[test]
+    fn tracks_position() {
+        let inner = Cursor::new(b"Tracked position".to_vec());
+        let mut stream = Retry::new(InterruptedSeeks {inner, interrupts: 2});
+        let mut out = [0u8; 4];
+        stream.read_exact(&mut out).unwrap();
+        assert_eq!(stream.tracked_position(), 4);
+        stream.seek(SeekFrom::Current(3)).unwrap();
+        assert_eq!(stream.tracked_position(), 7);
+        stream.read_exact(&mut out[..2]).unwrap();
+        stream.seek(SeekFrom::End(-3)).unwrap();
+        assert_eq!(stream.tracked_position(), 13);
+        stream.read_exact(&mut out[..2]).unwrap();
+        assert_eq!(stream.tracked_position(), 15);
+        assert_eq!(stream.stream_position().unwrap(), 15);
+    }
+
+    #[test]
+    fn retries_across_chain_boundary() {
+        let ops = vec![
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let first = PartialRead::new(&b"abc"[..], ops);
+        let second = PartialRead::new(&b"def"[..],
+            vec![PartialOp::Err(ErrorKind::Interrupted)]);
+        let mut reader = Retry::new(first.chain(second));
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "abcdef");
+    }
+
+    #[test]
+    fn disables_retrying_at_runtime() {
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(2),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(2),
+        ];
+        let mut reader = Retry::new(PartialRead::new(&b"abcd"[..], ops));
+        let mut out = [0u8; 2];
+        assert!(reader.retry_enabled());
+        assert_eq!(reader.read(&mut out).unwrap(), 2);
+        reader.set_retry_enabled(false);
+        assert!(!reader.retry_enabled());
+        assert_eq!(reader.read(&mut out).unwrap_err().kind(),
+            ErrorKind::Interrupted);
+        reader.set_retry_enabled(true);
+        assert_eq!(reader.read(&mut out).unwrap(), 2);
+        assert_eq!(&out, b"cd");
+    }
+
+    #[test]
+    fn wraps_fallibly_built_values() {
+        let mut reader = Retry::try_new(|| {
+            Ok::<_, String>(Cursor::new(b"abc".to_vec()))
+        }).unwrap();
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "abc");
+        let res = Retry::<Cursor<Vec<u8>>>::try_new(|| Err("no file"));
+        assert_eq!(res.unwrap_err(), "no file");
+    }
+
+    #[test]
+    fn reports_recovered_operations() {
+        let recoveries = Arc::new(Mutex::new(Vec::new()));
+        let ops = vec![
+            PartialOp::Limited(1),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(1),
+        ];
+        let mut reader = RetryBuilder::new()
+            .on_recovered({
+                let recoveries = recoveries.clone();
+                move |retries| recoveries.lock().unwrap().push(retries)
+            })
+            .build(PartialRead::new(&b"ab"[..], ops));
+        let mut out = [0u8; 1];
+        reader.read_exact(&mut out).unwrap();
+        assert!(recoveries.lock().unwrap().is_empty());
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(*recoveries.lock().unwrap(), vec![2]);
+    }
+
+    struct Stalling<T> {
+        inner: T,
+        stall_after: usize,
+    }
+
+    impl<T: Read> Read for Stalling<T> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.stall_after == 0 {
+                thread::sleep(Duration::from_millis(5));
+                return Err(ErrorKind::Interrupted.into());
+            }
+            let len = buf.len().min(self.stall_after);
+            let n = self.inner.read(&mut buf[..len])?;
+            self.stall_after -= n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn reads_partially_within_budget() {
+        let mut ops = vec![
+            PartialOp::Limited(3),
+            PartialOp::Limited(3),
+            PartialOp::Limited(1),
+        ];
+        ops.extend(vec![PartialOp::Err(ErrorKind::Interrupted); 100]);
+        let clock = ManualClock::new();
+        let mut reader = RetryBuilder::new()
+            .backoff(Backoff::Constant(Duration::from_millis(5)))
+            .clock(clock.clone())
+            .build(PartialRead::new(&b"Partial read"[..], ops));
+        let mut out = [0u8; 12];
+        let n = reader.read_within(&mut out, Duration::from_millis(50))
+            .unwrap();
+        assert_eq!(&out[..n], b"Partial");
+        assert_eq!(*clock.elapsed.lock().unwrap(), Duration::from_millis(50));
+        let err = reader.read_within(&mut out, Duration::from_millis(20))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+        assert_eq!(*clock.elapsed.lock().unwrap(), Duration::from_millis(70));
+        let inner = PartialRead::new(&b"Capped"[..], vec![
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ]);
+        let mut reader = RetryBuilder::new().max_retries(1).build(inner);
+        let n = reader.read_within(&mut out, Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(&out[..n], b"Cap");
+        assert_eq!(reader.read(&mut out).unwrap(), 3);
+    }
+
+    #[test]
+    fn keeps_bytes_read_within_budget_before_failure() {
+        let ops = vec![
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::InvalidData),
+        ];
+        let mut reader = Retry::new(PartialRead::new(&b"abcdef"[..], ops));
+        let mut out = [0u8; 6];
+        let n = reader.read_within(&mut out, Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(&out[..n], b"abc");
+        assert_eq!(reader.read(&mut out).unwrap_err().kind(),
+            ErrorKind::InvalidData);
+        assert_eq!(reader.read(&mut out).unwrap(), 3);
+        assert_eq!(&out[..3], b"def");
+    }
+
+    #[test]
+    fn reports_bytes_written_before_failure() {
+        let write = |ops| {
+            let sink = PartialWrite::new(Vec::new(), ops);
+            let err = Retry::new(sink).write_all_ctx(b"abcd").unwrap_err();
+            assert_eq!(err.error.kind(), ErrorKind::BrokenPipe);
+            err.bytes_written_before_failure
+        };
+        let fail = || PartialOp::Err(ErrorKind::BrokenPipe);
+        assert_eq!(write(vec![fail()]), 0);
+        assert_eq!(write(vec![
+            PartialOp::Limited(1),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(2),
+            fail(),
+        ]), 3);
+        let mut writer = Retry::new(Vec::new());
+        writer.write_all_ctx(b"abcd").unwrap();
+        assert_eq!(writer.into_inner(), b"abcd");
+    }
+
+    #[test]
+    fn forwards_formatted_text() {
+        use std::fmt::Write as _;
+
+        let mut writer = Retry::new(String::new());
+        let n = 1;
+        write!(writer, "{}-{}", n, n + 1).unwrap();
+        writer.write_char('!').unwrap();
+        assert_eq!(writer.into_inner(), "1-2!");
+    }
+
+    #[derive(Debug, Default)]
+    struct CountingWriter {
+        written: Vec<u8>,
+        calls: usize,
+        flushes: usize,
+        flushed_at: Vec<usize>,
+        interrupted_flushes: usize,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            if self.interrupted_flushes > 0 {
+                self.interrupted_flushes -= 1;
+                return Err(ErrorKind::Interrupted.into());
+            }
+            self.flushes += 1;
+            self.flushed_at.push(self.written.len());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn coalesces_small_writes() {
+        let mut writer = Retry::coalescing(CountingWriter::default(), 8);
+        for _ in 0..10 {
+            writer.write_all(b"abc").unwrap();
+        }
+        writer.write_all(b"Larger than threshold").unwrap();
+        writer.write_all(b"!").unwrap();
+        writer.flush().unwrap();
+        let inner = writer.into_inner();
+        assert_eq!(inner.written,
+            &b"abcabcabcabcabcabcabcabcabcabcLarger than threshold!"[..]);
+        assert_eq!(inner.calls, 7);
+    }
+
+    #[test]
+    fn writes_pending_bytes_before_seeking() {
+        let mut writer = Retry::coalescing(Cursor::new(Vec::new()), 8);
+        writer.write_all(b"abc").unwrap();
+        assert_eq!(writer.seek(SeekFrom::Start(1)).unwrap(), 1);
+        writer.write_all(b"X").unwrap();
+        assert_eq!(writer.stream_position().unwrap(), 2);
+        writer.flush().unwrap();
+        assert_eq!(writer.into_inner().into_inner(), b"aXc");
+    }
+
+    struct CorruptOnce<T> {
+        inner: T,
+        corrupt: bool,
+        seeks: Vec<SeekFrom>,
+    }
+
+    impl<T: Read> Read for CorruptOnce<T> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if mem::take(&mut self.corrupt) {
+                self.inner.read(&mut [0u8; 2])?;
+                return Err(ErrorKind::InvalidData.into());
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    impl<T: Seek> Seek for CorruptOnce<T> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.seeks.push(pos);
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn rewinds_and_retries_reads() {
+        let mut reader = Retry::new(CorruptOnce {
+            inner: Cursor::new(b"Rewind".to_vec()),
+            corrupt: true,
+            seeks: Vec::new(),
+        }).rewind_and_retry_on(ErrorKind::InvalidData);
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "Rewind");
+        assert!(reader.into_inner().seeks.contains(&SeekFrom::Start(0)));
+    }
+
+    #[test]
+    fn limits_rewinds_to_retry_limit() {
+        struct AlwaysCorrupt(Cursor<Vec<u8>>, u32);
+
+        impl Read for AlwaysCorrupt {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                self.1 += 1;
+                Err(ErrorKind::InvalidData.into())
+            }
+        }
+
+        impl Seek for AlwaysCorrupt {
+            fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+                self.0.seek(pos)
+            }
+        }
+
+        let mut reader = RetryBuilder::new()
+            .max_retries(3)
+            .build(AlwaysCorrupt(Cursor::new(b"Corrupt".to_vec()), 0))
+            .rewind_and_retry_on(ErrorKind::InvalidData);
+        let e = reader.read(&mut [0u8; 4]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert_eq!(reader.into_inner().1, 4);
+    }
+
+    #[test]
+    fn pipes_between_retrying_wrappers() {
+        let input = &b"Piped between wrappers"[..];
+        let read_ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(10),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let write_ops = vec![
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(4),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let mut src = Retry::new(PartialRead::new(input, read_ops));
+        let mut dst = Retry::new(PartialWrite::new(Vec::new(), write_ops));
+        assert_eq!(pipe(&mut src, &mut dst).unwrap(), input.len() as u64);
+        assert_eq!(dst.into_inner().into_inner(), input);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn counts_over_lifetime() {
+        let mut stream = Duplex {
+            reader: PartialRead::new(b"Lifetime", vec![
+                PartialOp::Err(ErrorKind::Interrupted),
+                PartialOp::Limited(3),
+            ]),
+            writer: PartialWrite::new(Vec::new(), vec![
+                PartialOp::Err(ErrorKind::Interrupted),
+                PartialOp::Limited(2),
+            ]),
+        };
+        let mut stream = Retry::new(&mut stream);
+        let mut out = [0u8; 5];
+        stream.read_exact(&mut out).unwrap();
+        stream.write_all(b"abc").unwrap();
+        assert_eq!(stream.retries(), 2);
+        assert_eq!(stream.bytes_read(), 5);
+        assert_eq!(stream.bytes_written(), 3);
+    }
+
+    #[test]
+    fn limits_line_length() {
+        let input = b"short\nmuch longer than allowed\nend".to_vec();
+        let ops = vec![
+            PartialOp::Limited(4),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let source = PartialRead::new(Cursor::new(input), ops);
+        let mut reader = Retry::new(BufReader::with_capacity(4, source));
+        let mut line = String::new();
+        assert_eq!(reader.read_line_limited(&mut line, 8).unwrap(), 6);
+        assert_eq!(line, "short\n");
+        let err = reader.read_line_limited(&mut line, 8).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(line, "short\n");
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "ger than allowed\nend");
+        let mut reader = Retry::new(&b"exactly\nat EOF!!"[..]);
+        assert_eq!(reader.read_line_limited(&mut line, 8).unwrap(), 8);
+        assert_eq!(reader.read_line_limited(&mut line, 8).unwrap(), 8);
+    }
+
+    #[test]
+    fn coalesces_retry_events() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let ops = vec![PartialOp::Err(ErrorKind::Interrupted); 10];
+        let mut reader = RetryBuilder::new()
+            .coalesce_retry_events(RetryEventCoalescing::Count(4))
+            .on_retry({
+                let calls = calls.clone();
+                move |info| calls.lock().unwrap()
+                    .push((info.retries(), info.suppressed()))
+            })
+            .build(PartialRead::new(&b"abc"[..], ops));
+        assert_eq!(reader.read(&mut [0u8; 3]).unwrap(), 3);
+        assert_eq!(*calls.lock().unwrap(),
+            vec![(0, 0), (4, 3), (8, 3), (9, 0)]);
+    }
+
+    #[test]
+    fn does_not_report_again_after_reported_last_retry() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let ops = vec![PartialOp::Err(ErrorKind::Interrupted); 3];
+        let mut reader = RetryBuilder::new()
+            .coalesce_retry_events(RetryEventCoalescing::Count(2))
+            .on_retry({
+                let calls = calls.clone();
+                move |info| calls.lock().unwrap()
+                    .push((info.retries(), info.suppressed()))
+            })
+            .build(PartialRead::new(&b"abc"[..], ops));
+        assert_eq!(reader.read(&mut [0u8; 3]).unwrap(), 3);
+        assert_eq!(*calls.lock().unwrap(), vec![(0, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn surfaces_would_block_by_default() {
+        let ops = vec![
+            PartialOp::Err(ErrorKind::WouldBlock),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(2),
+        ];
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let mut writer = RetryBuilder::new()
+            .on_give_up({
+                let attempts = attempts.clone();
+                move |_, n| attempts.lock().unwrap().push(n)
+            })
+            .build(PartialWrite::new(Vec::new(), ops));
+        assert_eq!(writer.write(b"abc").unwrap_err().kind(),
+            ErrorKind::WouldBlock);
+        assert_eq!(*attempts.lock().unwrap(), vec![1]);
+        assert_eq!(writer.write(b"abc").unwrap(), 2);
+    }
+
+    #[test]
+    fn expects_end_of_stream() {
+        let ops = vec![
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let mut reader = Retry::new(PartialRead::new(&b"end"[..], ops));
+        let mut out = [0u8; 3];
+        reader.read_exact(&mut out).unwrap();
+        reader.expect_eof().unwrap();
+        let ops = vec![
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let mut reader = Retry::new(PartialRead::new(&b"end!"[..], ops));
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(reader.expect_eof().unwrap_err().kind(),
+            ErrorKind::InvalidData);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"!");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn uses_socket_timeouts_as_deadlines() {
+        use std::net::{TcpListener, TcpStream};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap())
+            .unwrap();
+        stream.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+        stream.set_write_timeout(Some(Duration::from_millis(250))).unwrap();
+        let read_timeout = stream.read_timeout().unwrap();
+        let write_timeout = stream.write_timeout().unwrap();
+        assert!(read_timeout.is_some() && write_timeout.is_some());
+        let stream = Retry::from_socket(stream).unwrap();
+        assert_eq!(stream.read_deadline(), read_timeout);
+        assert_eq!(stream.write_deadline(), write_timeout);
+        let stream = TcpStream::connect(listener.local_addr().unwrap())
+            .unwrap();
+        let stream = Retry::from_socket(stream).unwrap();
+        assert_eq!(stream.read_deadline(), None);
+        assert_eq!(stream.write_deadline(), None);
+    }
+
+    #[test]
+    fn stops_retrying_after_deadline() {
+        let ops = vec![PartialOp::Err(ErrorKind::TimedOut); 1000];
+        let mut reader = RetryBuilder::new()
+            .retry_on(ErrorKind::TimedOut)
+            .backoff(Backoff::Constant(Duration::from_millis(10)))
+            .read_deadline(Duration::from_millis(30))
+            .build(PartialRead::new(&b"abc"[..], ops));
+        assert_eq!(reader.read(&mut [0u8; 3]).unwrap_err().kind(),
+            ErrorKind::TimedOut);
+    }
+
+    struct Uppercase<R> {
+        inner: R,
+        errors: u32,
+    }
+
+    impl<R: Read> Read for Uppercase<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf).inspect_err(|_| self.errors += 1)?;
+            buf[..n].make_ascii_uppercase();
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn retries_under_adapters() {
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(2),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let source = PartialRead::new(&b"adapted"[..], ops);
+        let mut reader = Retry::under(source, |inner| Uppercase {
+            inner,
+            errors: 0,
+        });
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "ADAPTED");
+        assert_eq!(reader.errors, 0);
+    }
+
+    #[test]
+    fn limits_refills_per_read_until() {
+        let ops = vec![
+            PartialOp::Limited(1),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ].into_iter().cycle().take(40).collect::<Vec<_>>();
+        let source = PartialRead::new(&b"drip feed;"[..], ops.clone());
+        let mut reader = Retry::new(BufReader::new(source));
+        let mut out = Vec::new();
+        let err = reader.read_until_max_refills(b';', &mut out, 4)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(out, b"drip");
+        let source = PartialRead::new(&b"drip feed;"[..], ops);
+        let mut reader = Retry::new(BufReader::new(source));
+        out.clear();
+        assert_eq!(reader.read_until_max_refills(b';', &mut out, 10).unwrap(),
+            10);
+    }
+
+    #[test]
+    fn keeps_last_error() {
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(1),
+        ];
+        let mut reader = Retry::new(PartialRead::new(&b"ab"[..], ops));
+        assert!(reader.last_error().is_none());
+        let mut out = [0u8; 1];
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(reader.last_error().unwrap().kind(), ErrorKind::Interrupted);
+        reader.read_exact(&mut out).unwrap();
+        assert!(reader.last_error().is_none());
+    }
+
+    #[test]
+    fn reads_vectored_into_uninitialized_buffers() {
+        let mut reader = Retry::new(ErrorsFirst {
+            errors: vec![ErrorKind::Interrupted.into()],
+            inner: &b"Vecto"[..],
+        });
+        let mut first = [MaybeUninit::<u8>::uninit(); 3];
+        let mut second = [MaybeUninit::<u8>::uninit(); 4];
+        let n = reader.read_vectored_uninit(&mut [&mut first, &mut second])
+            .unwrap();
+        assert_eq!(n, 5);
+        let read = first.iter().chain(&second[..2])
+            .map(|b| unsafe { b.assume_init() })
+            .collect::<Vec<_>>();
+        assert_eq!(read, b"Vecto");
+        let mut reader = Retry::new(&b"abc"[..]);
+        let mut first = [MaybeUninit::new(9u8); 2];
+        let mut second = [MaybeUninit::new(9u8); 2];
+        let n = reader.read_vectored_uninit(&mut [&mut first, &mut second])
+            .unwrap();
+        assert_eq!(n, 3);
+        let read = first.iter().chain(&second)
+            .map(|b| unsafe { b.assume_init() })
+            .collect::<Vec<_>>();
+        assert_eq!(read, b"abc\x09");
+    }
+
+    #[test]
+    fn skips_redundant_flushes() {
+        let mut writer = RetryBuilder::new()
+            .skip_redundant_flush(true)
+            .build(CountingWriter::default());
+        writer.flush().unwrap();
+        writer.write_all(b"abc").unwrap();
+        writer.flush().unwrap();
+        writer.flush().unwrap();
+        write!(writer, "{}", 1).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.into_inner().flushes, 2);
+    }
+
+    #[test]
+    fn shares_config_between_wrappers() {
+        let config = RetryConfig::builder()
+            .retry_on(ErrorKind::TimedOut)
+            .max_retries(2)
+            .build_config();
+        let read = |n| {
+            let ops = vec![PartialOp::Err(ErrorKind::TimedOut); n];
+            let source = PartialRead::new(&b"abc"[..], ops);
+            let mut reader = Retry::with_config(source, config.clone());
+            reader.read(&mut [0u8; 3]).map_err(|e| e.kind())
+        };
+        assert_eq!(read(2), Ok(3));
+        assert_eq!(read(3), Err(ErrorKind::TimedOut));
+        assert_eq!(Arc::strong_count(&config), 1);
+    }
+
+    #[test]
+    fn retries_results_of_iterators() {
+        let interrupted = || Err(io::Error::from(ErrorKind::Interrupted));
+        let items = vec![
+            interrupted(),
+            Ok(1),
+            interrupted(),
+            interrupted(),
+            Ok(2),
+            Err(ErrorKind::Other.into()),
+            interrupted(),
+        ];
+        let clock = ManualClock::new();
+        let builder = RetryBuilder::new()
+            .backoff(Backoff::Constant(Duration::from_millis(100)))
+            .clock(clock.clone());
+        let mut items = retry_results(items, builder);
+        assert_eq!(items.next().unwrap().unwrap(), 1);
+        assert_eq!(items.next().unwrap().unwrap(), 2);
+        assert_eq!(items.next().unwrap().unwrap_err().kind(), ErrorKind::Other);
+        assert!(items.next().is_none());
+        assert_eq!(*clock.elapsed.lock().unwrap(), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn bounds_coalescing_buffer() {
+        let ops = [
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(3),
+        ];
+        let ops = ops.iter().cloned().cycle().take(2000).collect::<Vec<_>>();
+        let mut writer = RetryBuilder::new()
+            .coalesce(64)
+            .max_buffer(16)
+            .build(Recording::new(PartialWrite::new(Vec::new(), ops)));
+        let data = (0..=255).cycle().take(1000).collect::<Vec<u8>>();
+        for chunk in data.chunks(50) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.flush().unwrap();
+        let largest = writer.operations().iter()
+            .filter_map(|op| match op {
+                Operation::Write(bytes) => Some(bytes.len()),
+                _ => None,
+            })
+            .max();
+        assert_eq!(largest, Some(16));
+        assert_eq!(writer.into_inner().into_inner().into_inner(), data);
+    }
+
+    #[test]
+    fn retries_connecting() {
+        let clock = ManualClock::new();
+        let builder = RetryBuilder::new()
+            .max_retries(2)
+            .backoff(Backoff::Constant(Duration::from_millis(100)))
+            .clock(clock.clone());
+        let mut attempts = 0;
+        let mut stream = retry_connect(builder.clone(), || {
+            attempts += 1;
+            match attempts {
+                1 | 2 => Err(io::Error::from(ErrorKind::Interrupted)),
+                _ => Ok(Cursor::new(b"connected".to_vec())),
+            }
+        }).unwrap();
+        let mut out = String::new();
+        stream.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "connected");
+        assert_eq!(*clock.elapsed.lock().unwrap(), Duration::from_millis(200));
+        let mut attempts = 0;
+        let e = retry_connect(builder, || -> io::Result<Cursor<Vec<u8>>> {
+            attempts += 1;
+            Err(io::Error::new(ErrorKind::Interrupted, attempts.to_string()))
+        }).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Interrupted);
+        assert_eq!(e.to_string(), "3");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pipes_through_child_processes() {
+        use std::process::{Command, Stdio};
+
+        let mut child = Command::new("cat")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = Retry::child_stdin(&mut child).unwrap();
+        let mut stdout = Retry::child_stdout(&mut child).unwrap();
+        assert!(Retry::child_stdin(&mut child).is_none());
+        stdin.write_all(b"Echoed by a child").unwrap();
+        drop(stdin);
+        let mut out = String::new();
+        stdout.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "Echoed by a child");
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn reads_frames() {
+        let input = &b"\0\0\0\x05hello\0\0\0\0\0\0\0\x03ab"[..];
+        let ops = vec![
+            PartialOp::Limited(2),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(4),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(1),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let mut reader = Retry::new(PartialRead::new(input, ops));
+        assert_eq!(reader.read_frame(5).unwrap(), b"hello");
+        assert_eq!(reader.read_frame(5).unwrap(), b"");
+        assert_eq!(reader.read_frame(5).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof);
+        let mut reader = Retry::new(&b"\0\0"[..]);
+        assert_eq!(reader.read_frame(5).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn reads_exact_vecs() {
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(2),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let mut reader = Retry::new(PartialRead::new(&b"recordtail"[..], ops));
+        assert_eq!(reader.read_exact_vec(6).unwrap(), b"record");
+        assert_eq!(reader.read_exact_vec(0).unwrap(), b"");
+        assert_eq!(reader.read_exact_vec(5).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn rejects_oversized_frames() {
+        let mut reader = Retry::new(&b"\0\0\x01\0payload"[..]);
+        assert_eq!(reader.read_frame(255).unwrap_err().kind(),
+            ErrorKind::InvalidData);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"payload");
+    }
+
+    #[test]
+    fn flushes_every_few_bytes() {
+        let sink = CountingWriter {
+            interrupted_flushes: 2,
+            ..CountingWriter::default()
+        };
+        let mut writer = RetryBuilder::new()
+            .flush_every(10)
+            .build(BufWriter::new(sink));
+        for _ in 0..4 {
+            writer.write_all(b"abcd").unwrap();
+        }
+        let sink = writer.into_inner().into_inner().unwrap();
+        assert_eq!(sink.written.len(), 16);
+        assert_eq!(sink.flushed_at, [12]);
+    }
+
+    struct Interrupting;
+
+    impl Read for Interrupting {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(ErrorKind::Interrupted.into())
+        }
+    }
+
+    #[test]
+    fn escalates_persistent_retries() {
+        let calls = Arc::new(Mutex::new(0));
+        let clock = ManualClock::new();
+        let mut reader = RetryBuilder::new()
+            .backoff(Backoff::Constant(Duration::from_millis(5)))
+            .clock(clock.clone())
+            .escalate_after(Duration::from_millis(20), {
+                let calls = calls.clone();
+                move || {
+                    let mut calls = calls.lock().unwrap();
+                    *calls += 1;
+                    if *calls == 1 {Escalate::Continue} else {Escalate::Fail}
+                }
+            })
+            .build(Interrupting);
+        assert_eq!(reader.read(&mut [0u8; 4]).unwrap_err().kind(),
+            ErrorKind::Interrupted);
+        assert_eq!(*clock.elapsed.lock().unwrap(), Duration::from_millis(40));
+        assert_eq!(*calls.lock().unwrap(), 2);
+        let mut reader = Retry::with_escalation(Interrupting,
+            Duration::from_millis(0), || Escalate::Fail);
+        assert_eq!(reader.read(&mut [0u8; 4]).unwrap_err().kind(),
+            ErrorKind::Interrupted);
+        let reconnects = Arc::new(Mutex::new(0));
+        let mut reader = RetryBuilder::new()
+            .max_retries(3)
+            .escalate_after(Duration::from_millis(0), || Escalate::Reconnect)
+            .build(Interrupting)
+            .reconnect_on(ErrorKind::ConnectionReset, {
+                let reconnects = reconnects.clone();
+                move || {
+                    *reconnects.lock().unwrap() += 1;
+                    Ok(Interrupting)
+                }
+            });
+        assert!(reader.read(&mut [0u8; 4]).is_err());
+        assert_eq!(*reconnects.lock().unwrap(), 3);
+    }
+
+    #[test]
+    fn retries_custom_operations() {
+        let mut wrapper = Retry::new(0);
+        let res = wrapper.retrying(|attempts| {
+            *attempts += 1;
+            match *attempts {
+                1 => Err(ErrorKind::Interrupted.into()),
+                _ => Ok(42),
+            }
+        });
+        assert_eq!(res.unwrap(), 42);
+        assert_eq!(wrapper.into_inner(), 2);
+        let mut wrapper = RetryBuilder::new().max_retries(1).build(());
+        let res: io::Result<()> =
+            wrapper.retrying(|_| Err(ErrorKind::Interrupted.into()));
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::Interrupted);
+    }
+
+    #[test]
+    fn maps_escaping_errors() {
+        fn map(e: io::Error) -> io::Error {
+            assert_eq!(e.kind(), ErrorKind::TimedOut);
+            io::Error::new(ErrorKind::WouldBlock, e)
+        }
+
+        let ops = vec![
+            PartialOp::Err(ErrorKind::TimedOut),
+            PartialOp::Err(ErrorKind::TimedOut),
+        ];
+        let mut reader = RetryBuilder::new()
+            .retry_on(ErrorKind::TimedOut)
+            .max_retries(1)
+            .map_error(map)
+            .build(PartialRead::new(&b"ok"[..], ops));
+        let mut out = [0u8; 2];
+        assert_eq!(reader.read(&mut out).unwrap_err().kind(),
+            ErrorKind::WouldBlock);
+        assert_eq!(reader.last_error().unwrap().kind(), ErrorKind::TimedOut);
+        assert_eq!(reader.read(&mut out).unwrap(), 2);
+        let ops = vec![PartialOp::Err(ErrorKind::TimedOut)];
+        let mut reader = RetryBuilder::new()
+            .retry_on(ErrorKind::TimedOut)
+            .max_retries(1)
+            .map_error(|_| unreachable!())
+            .build(PartialRead::new(&b"ok"[..], ops));
+        assert_eq!(reader.read(&mut out).unwrap(), 2);
+    }
+
+    #[test]
+    fn yields_while_retrying() {
+        let input = &b"Fair test"[..];
+        let ops = || {
+            (0..100)
+                .map(|_| PartialOp::Err(ErrorKind::Interrupted))
+                .chain(Some(PartialOp::Limited(4)))
+                .collect::<Vec<_>>()
+        };
+        let mut reader = RetryBuilder::new()
+            .fair(true)
+            .build(PartialRead::new(input, ops()));
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "Fair test");
+        let mut writer = RetryBuilder::new()
+            .yield_every(1)
+            .build(PartialWrite::new(Vec::new(), ops()));
+        writer.write_all(input).unwrap();
+        assert_eq!(writer.into_inner().into_inner(), input);
+    }
+
+    #[test]
+    fn unreads_bytes() {
+        let input = &b"Unread test"[..];
+        let ops = vec![
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let mut reader = Retry::new(BufReader::with_capacity(4,
+            PartialRead::new(input, ops)));
+        let mut word = [0u8; 6];
+        reader.read_exact(&mut word).unwrap();
+        assert_eq!(&word, b"Unread");
+        reader.unread(b"read");
+        reader.unread(b"Un");
+        assert_eq!(reader.fill_buf().unwrap(), b"Unread");
+        reader.consume(2);
+        assert_eq!(reader.available(), 4);
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "read test");
+    }
+
+    #[test]
+    fn enforces_min_throughput() {
+        let inner = PartialRead::new(&b"Slow read"[..],
+            vec![PartialOp::Limited(3)]);
+        let mut reader = Retry::with_min_throughput(
+            Stalling {inner, stall_after: 7}, 100, Duration::from_millis(30));
+        let mut out = Vec::new();
+        assert_eq!(reader.read_to_end(&mut out).unwrap_err().kind(),
+            ErrorKind::TimedOut);
+        assert_eq!(out, b"Slow re");
+        let mut reader = Retry::with_min_throughput(&b"Fast read"[..], 100,
+            Duration::from_millis(30));
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"Fast read");
+    }
+
+    /// Fails every other call that may perform IO with `Interrupted` before
+    /// reaching the inner value, like a shim over a reader that cannot be
+    /// interrupted.
+    struct Alternating<T> {
+        inner: T,
+        interrupt: bool,
+        filled: bool,
+    }
+
+    impl<T> Alternating<T> {
+        fn interrupt(&mut self) -> io::Result<()> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                Err(ErrorKind::Interrupted.into())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl<T: Read> Read for Alternating<T> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt()?;
+            let len = buf.len().min(5);
+            self.inner.read(&mut buf[..len])
+        }
+    }
+
+    impl<T: BufRead> BufRead for Alternating<T> {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            if !self.filled {
+                self.interrupt()?;
+                self.filled = true;
+            }
+            self.inner.fill_buf()
+        }
+
+        fn consume(&mut self, n: usize) {
+            self.filled = false;
+            self.inner.consume(n)
+        }
+    }
+
+    impl<T: Seek> Seek for Alternating<T> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.interrupt()?;
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn keeps_position_of_cursors_across_retries() {
+        let source = (0..=255).cycle().take(1000).collect::<Vec<u8>>();
+        let cursor = || Cursor::new(source.clone());
+        let mut reader = Retry::new(Alternating {
+            inner: cursor(),
+            interrupt: false,
+            filled: false,
+        });
+        let mut out = vec![0u8; 12];
+        assert_eq!(reader.read(&mut out).unwrap(), 5);
+        reader.read_exact(&mut out[5..]).unwrap();
+        assert_eq!(reader.stream_position().unwrap(), 12);
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, source);
+        assert_eq!(reader.into_inner().inner.position(), 1000);
+        let mut reader = Retry::new(Alternating {
+            inner: cursor(),
+            interrupt: false,
+            filled: false,
+        });
+        let mut out = Vec::new();
+        loop {
+            let n = {
+                let buf = reader.fill_buf().unwrap();
+                out.extend_from_slice(&buf[..buf.len().min(7)]);
+                buf.len().min(7)
+            };
+            if n == 0 {
+                break;
+            }
+            reader.consume(n);
+            assert_eq!(reader.stream_position().unwrap(), out.len() as u64);
+        }
+        assert_eq!(out, source);
+        let mut reader = Retry::new(BufReader::with_capacity(16, Alternating {
+            inner: cursor(),
+            interrupt: false,
+            filled: false,
+        }));
+        let mut out = vec![0u8; 100];
+        reader.read_exact(&mut out).unwrap();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, source);
+    }
+
+    #[derive(Debug)]
+    struct ManualClock {
+        start: Instant,
+        elapsed: Mutex<Duration>,
+    }
+
+    impl ManualClock {
+        fn new() -> Arc<Self> {
+            Arc::new(ManualClock {
+                start: Instant::now(),
+                elapsed: Mutex::new(Duration::from_secs(0)),
+            })
+        }
+
+        fn advance(&self, duration: Duration) {
+            *self.elapsed.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            self.start + *self.elapsed.lock().unwrap()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.advance(duration)
+        }
+    }
+
+    #[test]
+    fn retries_all_errors_during_startup_grace() {
+        let clock = ManualClock::new();
+        let reasons = Arc::new(Mutex::new(Vec::new()));
+        let ops = vec![
+            PartialOp::Err(ErrorKind::NotFound),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(2),
+            PartialOp::Err(ErrorKind::NotFound),
+        ];
+        let mut reader = RetryBuilder::new()
+            .backoff(Backoff::Constant(Duration::from_millis(300)))
+            .startup_grace(Duration::from_secs(1))
+            .clock(clock.clone())
+            .on_retry({
+                let reasons = reasons.clone();
+                move |info| reasons.lock().unwrap().push(info.reason())
+            })
+            .build(PartialRead::new(&b"Grace"[..], ops));
+        let mut out = [0u8; 2];
+        assert_eq!(reader.read(&mut out).unwrap(), 2);
+        assert_eq!(*reasons.lock().unwrap(),
+            [RetryReason::StartupGrace, RetryReason::Interrupted]);
+        assert_eq!(*clock.elapsed.lock().unwrap(), Duration::from_millis(600));
+        clock.advance(Duration::from_millis(400));
+        assert_eq!(reader.read(&mut out).unwrap_err().kind(),
+            ErrorKind::NotFound);
+        assert_eq!(reasons.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn limits_startup_grace_retries() {
+        let ops = vec![PartialOp::Err(ErrorKind::NotFound); 4];
+        let mut reader = RetryBuilder::new()
+            .max_retries(2)
+            .startup_grace(Duration::from_secs(60))
+            .build(Recording::new(PartialRead::new(&b"Grace"[..], ops)));
+        let mut out = [0u8; 5];
+        assert_eq!(reader.read(&mut out).unwrap_err().kind(),
+            ErrorKind::NotFound);
+        assert_eq!(reader.operations().len(), 3);
+        let overrides = RetryOverrides {
+            kinds: Some(vec![ErrorKind::TimedOut]),
+            ..RetryOverrides::default()
+        };
+        assert_eq!(reader.read_with(&mut out, overrides).unwrap(), 5);
+        assert_eq!(reader.operations().len(), 5);
+    }
+
+    // Sockets whose receive timeout expires fail with `EAGAIN`, i.e.
+    // `WouldBlock`, on Unix.
+    #[cfg(unix)]
+    #[test]
+    fn retries_socket_timeouts() {
+        use std::net::{TcpListener, TcpStream};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap())
+            .unwrap();
+        let clock = ManualClock::new();
+        let retries = Arc::new(Mutex::new(0));
+        let mut server = RetryBuilder::new()
+            .kinds(vec![ErrorKind::WouldBlock])
+            .max_retries(2)
+            .backoff(Backoff::Constant(Duration::from_millis(100)))
+            .clock(clock.clone())
+            .on_retry({
+                let retries = retries.clone();
+                move |_| *retries.lock().unwrap() += 1
+            })
+            .build(listener.accept().unwrap().0);
+        server.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+        server.set_write_timeout(Some(Duration::from_millis(10))).unwrap();
+        assert_eq!(server.read(&mut [0u8; 4]).unwrap_err().kind(),
+            ErrorKind::WouldBlock);
+        assert_eq!(*retries.lock().unwrap(), 2);
+        assert_eq!(*clock.elapsed.lock().unwrap(), Duration::from_millis(200));
+        // The kernel may round the timeouts up.
+        let server = server.into_inner();
+        let timeout = Duration::from_millis(10);
+        assert!(server.read_timeout().unwrap().unwrap() >= timeout);
+        assert!(server.write_timeout().unwrap().unwrap() >= timeout);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn takes_stats() {
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(3),
+        ];
+        let mut stream = Retry::new(Duplex {
+            reader: PartialRead::new(&b"Stats"[..], ops.clone()),
+            writer: PartialWrite::new(Vec::new(), ops),
+        });
+        let mut out = [0u8; 5];
+        stream.read_exact(&mut out).unwrap();
+        stream.write_all(b"ab").unwrap();
+        let stats = stream.take_stats();
+        assert_eq!(stats.retries, 2);
+        assert_eq!(stats.bytes_read, 5);
+        assert_eq!(stats.bytes_written, 2);
+        assert_eq!(stats.last_error, Some(ErrorKind::Interrupted));
+        assert_eq!(stream.retries(), 0);
+        let stats = stream.take_stats();
+        assert_eq!((stats.retries, stats.bytes_read, stats.bytes_written),
+            (0, 0, 0));
+    }
+
+    #[test]
+    fn flushes_nested_wrappers() {
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(4),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Unlimited,
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let retries = Arc::new(Mutex::new(0));
+        let bottom = Retry::on_retry(PartialWrite::new(Vec::new(), ops), {
+            let retries = retries.clone();
+            move |_| *retries.lock().unwrap() += 1
+        });
+        let mut writer = Retry::new(BufWriter::new(bottom));
+        writer.write_all(b"Nested flush").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(*retries.lock().unwrap(), 4);
+        let bottom = writer.into_inner().into_inner().unwrap().into_inner();
+        assert_eq!(bottom.into_inner(), b"Nested flush");
+    }
+
+    #[test]
+    fn replaces_inner_value() {
+        let mut writer = RetryBuilder::new()
+            .coalesce(16)
+            .build(PartialWrite::new(Vec::new(), vec![
+                PartialOp::Err(ErrorKind::Interrupted),
+            ]))
+            .flush_on_replace(true);
+        writer.write_all(b"Old bytes").unwrap();
+        let old = writer.replace_inner(PartialWrite::new(Vec::new(), vec![]));
+        assert_eq!(old.into_inner(), b"Old bytes");
+        writer.write_all(b"New bytes").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.into_inner().into_inner(), b"New bytes");
+        let mut writer = Retry::new(Vec::new());
+        writer.write_all(b"Old").unwrap();
+        assert_eq!(writer.replace_inner(Vec::new()), b"Old");
+        writer.write_all(b"New").unwrap();
+        assert_eq!(writer.into_inner(), b"New");
+    }
+
+    #[cfg(feature = "mio")]
+    #[test]
+    fn registers_with_mio() {
+        use mio::net::{TcpListener, TcpStream};
+        use mio::{Events, Poll, Token};
+
+        let mut poll = Poll::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        let mut stream = Retry::new(
+            TcpStream::connect(listener.local_addr().unwrap()).unwrap());
+        poll.registry()
+            .register(&mut stream, Token(0), mio::Interest::WRITABLE)
+            .unwrap();
+        let mut events = Events::with_capacity(4);
+        while events.is_empty() {
+            poll.poll(&mut events, Some(Duration::from_secs(5))).unwrap();
+        }
+        assert_eq!(events.iter().next().unwrap().token(), Token(0));
+        poll.registry()
+            .reregister(&mut stream, Token(1), mio::Interest::READABLE)
+            .unwrap();
+        assert_eq!(stream.read(&mut [0u8; 4]).unwrap_err().kind(),
+            ErrorKind::WouldBlock);
+        poll.registry().deregister(&mut stream).unwrap();
+    }
+
+    #[test]
+    fn reads_remaining_bytes_into_buffer() {
+        let ops = vec![
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let mut reader = Retry::new(PartialRead::new(&b"remaining"[..], ops));
+        let mut buf = b"Some ".to_vec();
+        assert_eq!(reader.read_remaining_into(&mut buf, 6).unwrap(), 6);
+        assert_eq!(buf, b"Some remain");
+        assert_eq!(reader.read_remaining_into(&mut buf, 6).unwrap(), 3);
+        assert_eq!(buf, b"Some remaining");
+        assert_eq!(reader.read_remaining_into(&mut buf, 6).unwrap(), 0);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn counts_operations() {
+        let ops = vec![
+            PartialOp::Limited(2),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(2),
+            PartialOp::Limited(2),
+        ];
+        let mut reader = Retry::new(PartialRead::new(&b"Counts"[..], ops));
+        let mut out = [0u8; 2];
+        for _ in 0..3 {
+            assert_eq!(reader.read(&mut out).unwrap(), 2);
+        }
+        assert_eq!(reader.operation_count(), 3);
+        assert_eq!(reader.retries(), 2);
+        assert_eq!(reader.take_stats().operations, 3);
+    }
+
+    #[test]
+    fn sends_events() {
+        use std::sync::mpsc;
+
+        let (sender, receiver) = mpsc::sync_channel(8);
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::NotFound),
+        ];
+        let mut reader = RetryBuilder::new()
+            .events(sender)
+            .build(PartialRead::new(&b"Events"[..], ops));
+        let mut out = [0u8; 6];
+        assert_eq!(reader.read(&mut out).unwrap(), 3);
+        assert_eq!(reader.read(&mut out).unwrap_err().kind(),
+            ErrorKind::NotFound);
+        assert_eq!(reader.read(&mut out).unwrap(), 3);
+        drop(reader);
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), [
+            RetryEvent::Retried {kind: ErrorKind::Interrupted, attempt: 1},
+            RetryEvent::Retried {kind: ErrorKind::Interrupted, attempt: 2},
+            RetryEvent::Progress {total: 3},
+            RetryEvent::GaveUp {kind: ErrorKind::NotFound},
+            RetryEvent::Progress {total: 6},
+        ]);
+    }
+
+    #[test]
+    fn reads_lines_lossily() {
+        let input = &b"valid\nin\xffvalid\nlast"[..];
+        let ops = vec![
+            PartialOp::Limited(8),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let mut reader = Retry::new(BufReader::new(PartialRead::new(input,
+            ops)));
+        let mut line = String::new();
+        assert_eq!(reader.read_line_lossy(&mut line).unwrap(), 6);
+        assert_eq!(line, "valid\n");
+        line.clear();
+        assert_eq!(reader.read_line_lossy(&mut line).unwrap(), 9);
+        assert_eq!(line, "in\u{fffd}valid\n");
+        line.clear();
+        assert_eq!(reader.read_line_lossy(&mut line).unwrap(), 4);
+        assert_eq!(line, "last");
+    }
+
+    #[test]
+    fn gives_raw_access_in_scope() {
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let mut reader = Retry::new(PartialRead::new(&b"Raw"[..], ops));
+        let mut out = [0u8; 3];
+        {
+            let mut raw = reader.raw_scope();
+            assert_eq!(raw.read(&mut out).unwrap_err().kind(),
+                ErrorKind::Interrupted);
+        }
+        assert_eq!(reader.read(&mut out).unwrap(), 3);
+        assert_eq!(&out, b"Raw");
+    }
+
+    #[test]
+    fn caps_total_backoff() {
+        let clock = ManualClock::new();
+        let retries = Arc::new(Mutex::new(0));
+        let mut reader = RetryBuilder::new()
+            .backoff(Backoff::Exponential {
+                initial: Duration::from_millis(100),
+                max: Duration::from_secs(10),
+            })
+            .max_backoff_total(Duration::from_millis(500))
+            .clock(clock.clone())
+            .on_retry({
+                let retries = retries.clone();
+                move |_| *retries.lock().unwrap() += 1
+            })
+            .build(Interrupting);
+        assert_eq!(reader.read(&mut [0u8; 4]).unwrap_err().kind(),
+            ErrorKind::Interrupted);
+        assert_eq!(*retries.lock().unwrap(), 2);
+        assert_eq!(*clock.elapsed.lock().unwrap(), Duration::from_millis(300));
+    }
+
+    struct BrokenPipe;
+
+    impl Write for BrokenPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    #[test]
+    fn ignores_broken_pipes() {
+        let reports = Arc::new(Mutex::new(0));
+        let mut writer = RetryBuilder::new()
+            .ignore_broken_pipe(true)
+            .on_broken_pipe({
+                let reports = reports.clone();
+                move || *reports.lock().unwrap() += 1
+            })
+            .build(BrokenPipe);
+        assert_eq!(writer.write(b"lost").unwrap(), 4);
+        writer.write_all(b"lost as well").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(*reports.lock().unwrap(), 1);
+        let give_ups = Arc::new(Mutex::new(0));
+        let mut writer = RetryBuilder::new()
+            .ignore_broken_pipe(true)
+            .map_error(|e| io::Error::new(ErrorKind::ConnectionAborted, e))
+            .on_give_up({
+                let give_ups = give_ups.clone();
+                move |_, _| *give_ups.lock().unwrap() += 1
+            })
+            .build(BrokenPipe);
+        writer.write_all(b"lost").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(*give_ups.lock().unwrap(), 0);
+        #[cfg(feature = "stats")]
+        assert_eq!(writer.take_stats().bytes_written, 4);
+        let mut writer = Retry::new(BrokenPipe);
+        assert_eq!(writer.write(b"lost").unwrap_err().kind(),
+            ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn drains_buffer() {
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(6),
+            PartialOp::Limited(6),
+        ];
+        let mut reader = Retry::new(BufReader::new(PartialRead::new(
+            &b"HELLO\nbinary data"[..], ops)));
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "HELLO\n");
+        reader.unread(b"!");
+        assert_eq!(reader.drain_buffer().unwrap(), b"!");
+        assert!(reader.drain_buffer().unwrap().is_empty());
+        assert_eq!(reader.fill_buf().unwrap(), b"binary");
+        reader.consume(3);
+        assert_eq!(reader.drain_buffer().unwrap(), b"ary");
+        let mut rest = Vec::new();
+        let mut source = reader.into_inner().into_inner();
+        source.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b" data");
+    }
+
+    #[derive(Debug)]
+    struct Busy {
+        transient: bool,
+    }
+
+    impl std::fmt::Display for Busy {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("busy")
+        }
+    }
+
+    impl std::error::Error for Busy {}
+
+    struct BusyFirst<T> {
+        inner: T,
+        errors: Vec<Busy>,
+    }
+
+    impl<T: Read> Read for BusyFirst<T> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.errors.pop() {
+                Some(e) => Err(io::Error::other(e)),
+                None => self.inner.read(buf),
+            }
+        }
+    }
+
+    #[test]
+    fn retries_downcast_errors() {
+        let reader = |errors| {
+            RetryBuilder::new()
+                .retry_if_downcasts(|e: &Busy| e.transient)
+                .build(BusyFirst {inner: &b"Busy"[..], errors})
+        };
+        let mut out = [0u8; 4];
+        let mut transient = reader(vec![Busy {transient: true}]);
+        assert_eq!(transient.read(&mut out).unwrap(), 4);
+        let mut permanent = reader(vec![Busy {transient: false}]);
+        let e = permanent.read(&mut out).unwrap_err();
+        assert!(e.get_ref().unwrap().downcast_ref::<Busy>().is_some());
+        assert_eq!(permanent.read(&mut out).unwrap(), 4);
+    }
+
+    #[derive(Debug)]
+    struct CountingReader<R> {
+        inner: R,
+        calls: usize,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.calls += 1;
+            self.inner.read(buf)
+        }
+    }
+
+    #[test]
+    fn read_buffer_coalesces_small_reads() {
+        let data = (0..=255).cycle().take(1000).collect::<Vec<u8>>();
+        let mut ops = vec![PartialOp::Err(ErrorKind::Interrupted)];
+        ops.extend(std::iter::repeat_n(PartialOp::Unlimited, 100));
+        let inner = CountingReader {
+            inner: PartialRead::new(&data[..], ops),
+            calls: 0,
+        };
+        let mut reader = RetryBuilder::new().read_buffer(256).build(inner);
+        let mut out = Vec::new();
+        let mut chunk = [0u8; 3];
+        let mut reads = 0;
+        loop {
+            reads += 1;
+            match reader.read(&mut chunk).unwrap() {
+                0 => break,
+                n => out.extend_from_slice(&chunk[..n]),
+            }
+        }
+        assert_eq!(out, data);
+        assert!(reads > 300);
+        assert_eq!(reader.into_inner().calls, 6);
+    }
+
+    #[test]
+    fn read_with_overrides_settings_for_one_call() {
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Err(ErrorKind::TimedOut),
+        ];
+        let mut reader = Retry::new(PartialRead::new(&b"data"[..], ops));
+        let mut buf = [0u8; 4];
+        let no_retry = RetryOverrides {
+            max_attempts: Some(1),
+            ..RetryOverrides::default()
+        };
+        let e = reader.read_with(&mut buf, no_retry).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Interrupted);
+        let timed_out = RetryOverrides {
+            kinds: Some(vec![ErrorKind::Interrupted, ErrorKind::TimedOut]),
+            ..RetryOverrides::default()
+        };
+        assert_eq!(reader.read_with(&mut buf[..1], timed_out).unwrap(), 1);
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Err(ErrorKind::TimedOut),
+        ];
+        *reader.raw_scope() = PartialRead::new(&b"data"[..], ops);
+        let e = reader.read(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+    }
+
+    #[test]
+    fn reports_refills() {
+        let ops = [
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Unlimited,
+        ];
+        let ops = ops.iter().cloned().cycle().take(30).collect::<Vec<_>>();
+        let inner = PartialRead::new(&b"0123456789"[..], ops);
+        let fills = Arc::new(Mutex::new(Vec::new()));
+        let mut reader = {
+            let fills = fills.clone();
+            Retry::on_refill(BufReader::with_capacity(4, inner), move |n| {
+                fills.lock().unwrap().push(n)
+            })
+        };
+        assert_eq!(reader.fill_buf().unwrap(), b"0123");
+        reader.consume(1);
+        assert_eq!(reader.fill_buf().unwrap(), b"123");
+        let mut out = Vec::new();
+        reader.read_until(b'\n', &mut out).unwrap();
+        assert_eq!(out, b"123456789");
+        assert_eq!(*fills.lock().unwrap(), [4, 4, 2, 0]);
+        fills.lock().unwrap().clear();
+        let mut reader = {
+            let fills = fills.clone();
+            Retry::on_refill(BufReader::with_capacity(4, &b"abcdefghij"[..]),
+                move |n| fills.lock().unwrap().push(n))
+        };
+        let mut out = [0u8; 1];
+        reader.read_exact(&mut out).unwrap();
+        assert!(fills.lock().unwrap().is_empty());
+        assert_eq!(reader.fill_buf().unwrap(), b"bcd");
+        reader.consume(3);
+        assert_eq!(reader.fill_buf().unwrap(), b"efgh");
+        assert_eq!(*fills.lock().unwrap(), [3, 4]);
+        reader.consume(1);
+        assert_eq!(reader.fill_to_capacity().unwrap(), b"fghi");
+        assert_eq!(*fills.lock().unwrap(), [3, 4, 2]);
+    }
+
+    #[test]
+    fn reads_lines_into_reused_buffers() {
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(4),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let input = &b"a longer line\nshort\n\xff\nend"[..];
+        let inner = BufReader::new(PartialRead::new(input, ops));
+        let mut reader = Retry::new(inner);
+        let mut line = String::from("stale");
+        assert_eq!(reader.read_line_into(&mut line).unwrap(), 14);
+        assert_eq!(line, "a longer line\n");
+        let capacity = line.capacity();
+        assert_eq!(reader.read_line_into(&mut line).unwrap(), 6);
+        assert_eq!(line, "short\n");
+        assert_eq!(line.capacity(), capacity);
+        let e = reader.read_line_into(&mut line).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert_eq!(line, "");
+        assert_eq!(reader.read_line_into(&mut line).unwrap(), 3);
+        assert_eq!(line, "end");
+        assert_eq!(reader.read_line_into(&mut line).unwrap(), 0);
+        assert_eq!(line, "");
     }
 }
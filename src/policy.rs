@@ -0,0 +1,710 @@
+// Copyright (C) 2018 Stephane Raux. Distributed under the MIT license.
+
+//! Policies deciding which errors are retried.
+
+use crate::{Backoff, RetryProfile};
+use std::error::Error;
+use std::fmt;
+use std::io::{ErrorKind, self};
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// Decides whether a failed operation should be attempted again.
+pub trait RetryPolicy {
+    /// Returns whether to retry an operation that failed with `error` after
+    /// `retries` previous retries of the same operation.
+    fn should_retry(&self, error: &io::Error, retries: u32) -> bool;
+
+    /// Returns how long to wait before retrying an operation that failed with
+    /// `error` after `retries` previous retries.
+    ///
+    /// Defaults to retrying immediately.
+    fn backoff(&self, error: &io::Error, retries: u32) -> Duration {
+        let _ = (error, retries);
+        Duration::from_secs(0)
+    }
+
+    /// Returns why an error for which `should_retry` returned `true` is
+    /// retried.
+    ///
+    /// Defaults to [`RetryReason::Interrupted`] for errors of kind
+    /// `Interrupted` and [`RetryReason::Predicate`] otherwise.
+    fn retry_reason(&self, error: &io::Error, retries: u32) -> RetryReason {
+        let _ = retries;
+        if error.kind() == ErrorKind::Interrupted {
+            RetryReason::Interrupted
+        } else {
+            RetryReason::Predicate
+        }
+    }
+
+    /// Returns the maximum number of retries of an operation allowed by the
+    /// policy, whatever the error, or `None` if it is not bounded.
+    ///
+    /// Retries that do not consult [`should_retry`](RetryPolicy::should_retry),
+    /// like reconnecting with
+    /// [`Retry::reconnect_on`](crate::Retry::reconnect_on) or rewinding with
+    /// [`Retry::rewind_and_retry_on`](crate::Retry::rewind_and_retry_on), are
+    /// limited by this. Defaults to `None`.
+    fn retry_limit(&self) -> Option<u32> {
+        None
+    }
+
+    /// Returns a policy retrying errors retried by either `self` or `other`.
+    ///
+    /// `other` is only consulted for errors that `self` does not retry. The
+    /// backoff and reason are those of the policy that approved the retry.
+    fn or<Q>(self, other: Q) -> Or<Self, Q>
+    where
+        Self: Sized,
+        Q: RetryPolicy,
+    {
+        Or {first: self, second: other}
+    }
+
+    /// Returns a policy retrying errors retried by both `self` and `other`.
+    ///
+    /// The longest of the two backoffs is used and the reason is the one given
+    /// by `self`.
+    fn and<Q>(self, other: Q) -> And<Self, Q>
+    where
+        Self: Sized,
+        Q: RetryPolicy,
+    {
+        And {first: self, second: other}
+    }
+}
+
+impl dyn RetryPolicy {
+    /// Returns a policy retrying an operation when `f` returns `true`.
+    ///
+    /// This is [`from_fn`], reachable from the trait as
+    /// `<dyn RetryPolicy>::from_fn`. The policy returned is not a trait
+    /// object, so it is `Send` and `Sync` whenever `f` is.
+    ///
+    /// ```
+    /// use retrio::policy::RetryPolicy;
+    /// use std::io::ErrorKind;
+    ///
+    /// let policy = <dyn RetryPolicy>::from_fn(|e, retries| {
+    ///     e.kind() == ErrorKind::TimedOut && retries < 3
+    /// });
+    /// assert!(policy.should_retry(&ErrorKind::TimedOut.into(), 0));
+    /// ```
+    pub fn from_fn<F>(f: F) -> FnPolicy<F>
+    where
+        F: Fn(&io::Error, u32) -> bool,
+    {
+        from_fn(f)
+    }
+}
+
+impl<P: RetryPolicy + ?Sized> RetryPolicy for &P {
+    fn should_retry(&self, error: &io::Error, retries: u32) -> bool {
+        (**self).should_retry(error, retries)
+    }
+
+    fn backoff(&self, error: &io::Error, retries: u32) -> Duration {
+        (**self).backoff(error, retries)
+    }
+
+    fn retry_reason(&self, error: &io::Error, retries: u32) -> RetryReason {
+        (**self).retry_reason(error, retries)
+    }
+
+    fn retry_limit(&self) -> Option<u32> {
+        (**self).retry_limit()
+    }
+}
+
+impl<P: RetryPolicy + ?Sized> RetryPolicy for Box<P> {
+    fn should_retry(&self, error: &io::Error, retries: u32) -> bool {
+        (**self).should_retry(error, retries)
+    }
+
+    fn backoff(&self, error: &io::Error, retries: u32) -> Duration {
+        (**self).backoff(error, retries)
+    }
+
+    fn retry_reason(&self, error: &io::Error, retries: u32) -> RetryReason {
+        (**self).retry_reason(error, retries)
+    }
+
+    fn retry_limit(&self) -> Option<u32> {
+        (**self).retry_limit()
+    }
+}
+
+/// Why an operation is retried.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RetryReason {
+    /// The error is of kind `Interrupted`.
+    Interrupted,
+    /// The kind of the error is configured to be retried.
+    Kind,
+    /// The raw OS error code of the error is configured to be retried.
+    OsError,
+    /// A custom policy decided to retry the error.
+    Predicate,
+    /// `fill_buf` returned an empty buffer and empty fills are configured to
+    /// be retried.
+    EmptyFill,
+    /// The inner value is replaced with a new connection before retrying.
+    Reconnect,
+    /// The inner value is rewound to where the failed read started before
+    /// retrying.
+    Rewind,
+    /// The inner value returned an error of kind `WouldBlock` and is waited
+    /// on until it is ready before retrying.
+    WouldBlock,
+    /// The error occurred during the startup grace period, when all errors
+    /// are retried.
+    StartupGrace,
+    /// `read_exact` reached the end of the stream before filling its buffer
+    /// and the end of the stream is configured to be retried.
+    Eof,
+}
+
+/// Policy configured by kinds of errors, raw OS error codes, a maximum number
+/// of retries and a backoff.
+///
+/// By default, only errors of kind `Interrupted` are retried, as many times as
+/// needed and without waiting between attempts.
+#[derive(Clone, Debug)]
+pub struct DefaultPolicy {
+    kinds: Vec<ErrorKind>,
+    os_errors: Vec<i32>,
+    max_retries: Option<u32>,
+    backoff: Backoff,
+}
+
+impl DefaultPolicy {
+    /// Returns the default policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Retries errors of the given kind in addition to the ones already
+    /// configured.
+    pub fn retry_on(mut self, kind: ErrorKind) -> Self {
+        if !self.kinds.contains(&kind) {
+            self.kinds.push(kind);
+        }
+        self
+    }
+
+    /// Retries errors of the given kinds only, replacing the ones already
+    /// configured.
+    pub fn kinds<I>(mut self, kinds: I) -> Self
+    where
+        I: IntoIterator<Item = ErrorKind>,
+    {
+        self.kinds.clear();
+        kinds.into_iter().fold(self, DefaultPolicy::retry_on)
+    }
+
+    /// Retries errors with the given raw OS error code in addition to the ones
+    /// already configured.
+    pub fn retry_on_os_error(mut self, code: i32) -> Self {
+        if !self.os_errors.contains(&code) {
+            self.os_errors.push(code);
+        }
+        self
+    }
+
+    /// Gives up after `n` retries of the same operation.
+    pub fn max_retries(mut self, n: u32) -> Self {
+        self.max_retries = Some(n);
+        self
+    }
+
+    /// Sets how long to wait before each retry.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+}
+
+impl Default for DefaultPolicy {
+    fn default() -> Self {
+        DefaultPolicy {
+            kinds: vec![ErrorKind::Interrupted],
+            os_errors: Vec::new(),
+            max_retries: None,
+            backoff: Backoff::None,
+        }
+    }
+}
+
+impl From<RetryProfile> for DefaultPolicy {
+    fn from(profile: RetryProfile) -> Self {
+        match profile {
+            RetryProfile::Aggressive => DefaultPolicy {
+                kinds: vec![
+                    ErrorKind::Interrupted,
+                    ErrorKind::WouldBlock,
+                    ErrorKind::TimedOut,
+                ],
+                os_errors: Vec::new(),
+                max_retries: Some(10),
+                backoff: Backoff::Exponential {
+                    initial: Duration::from_millis(1),
+                    max: Duration::from_millis(100),
+                },
+            },
+            RetryProfile::Conservative => DefaultPolicy {
+                kinds: vec![ErrorKind::Interrupted],
+                os_errors: Vec::new(),
+                max_retries: Some(3),
+                backoff: Backoff::None,
+            },
+            RetryProfile::None => DefaultPolicy {
+                kinds: Vec::new(),
+                os_errors: Vec::new(),
+                max_retries: Some(0),
+                backoff: Backoff::None,
+            },
+        }
+    }
+}
+
+impl RetryPolicy for DefaultPolicy {
+    // `Option::is_none_or` would require Rust 1.82.
+    #[allow(clippy::unnecessary_map_or)]
+    fn should_retry(&self, error: &io::Error, retries: u32) -> bool {
+        let os_error = error.raw_os_error()
+            .is_some_and(|code| self.os_errors.contains(&code));
+        (os_error || self.kinds.contains(&error.kind()))
+            && self.max_retries.map_or(true, |max| retries < max)
+    }
+
+    fn backoff(&self, _: &io::Error, retries: u32) -> Duration {
+        self.backoff.delay(retries)
+    }
+
+    fn retry_reason(&self, error: &io::Error, _: u32) -> RetryReason {
+        let os_error = error.raw_os_error()
+            .is_some_and(|code| self.os_errors.contains(&code));
+        match error.kind() {
+            _ if os_error => RetryReason::OsError,
+            ErrorKind::Interrupted => RetryReason::Interrupted,
+            _ => RetryReason::Kind,
+        }
+    }
+
+    fn retry_limit(&self) -> Option<u32> {
+        self.max_retries
+    }
+}
+
+/// Policy retrying errors of the configured kinds, each with its own maximum
+/// number of retries and backoff.
+///
+/// Rules are added with [`on_kind`](KindPolicy::on_kind) and refined with
+/// [`max`](KindPolicy::max) and [`backoff`](KindPolicy::backoff), which apply
+/// to the rule added last. Errors of other kinds are not retried.
+///
+/// ```
+/// use retrio::Backoff;
+/// use retrio::policy::KindPolicy;
+/// use std::io::ErrorKind;
+/// use std::time::Duration;
+///
+/// let policy = KindPolicy::new()
+///     .on_kind(ErrorKind::Interrupted)
+///     .on_kind(ErrorKind::WouldBlock)
+///     .max(5)
+///     .backoff(Backoff::Constant(Duration::from_millis(1)))
+///     .on_kind(ErrorKind::TimedOut)
+///     .max(2)
+///     .backoff(Backoff::Constant(Duration::from_millis(100)));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct KindPolicy {
+    rules: Vec<KindRule>,
+}
+
+#[derive(Clone, Debug)]
+struct KindRule {
+    kind: ErrorKind,
+    max_retries: Option<u32>,
+    backoff: Backoff,
+}
+
+impl KindPolicy {
+    /// Returns a policy retrying no errors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Retries errors of the given kind as many times as needed and without
+    /// waiting between attempts, replacing any rule for this kind.
+    pub fn on_kind(mut self, kind: ErrorKind) -> Self {
+        self.rules.retain(|rule| rule.kind != kind);
+        self.rules.push(KindRule {
+            kind,
+            max_retries: None,
+            backoff: Backoff::None,
+        });
+        self
+    }
+
+    /// Gives up after `n` retries of the same operation for the rule added
+    /// last.
+    ///
+    /// This has no effect if no rule was added.
+    pub fn max(mut self, n: u32) -> Self {
+        if let Some(rule) = self.rules.last_mut() {
+            rule.max_retries = Some(n);
+        }
+        self
+    }
+
+    /// Sets how long to wait before each retry for the rule added last.
+    ///
+    /// This has no effect if no rule was added.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        if let Some(rule) = self.rules.last_mut() {
+            rule.backoff = backoff;
+        }
+        self
+    }
+
+    fn rule(&self, error: &io::Error) -> Option<&KindRule> {
+        self.rules.iter().find(|rule| rule.kind == error.kind())
+    }
+}
+
+impl RetryPolicy for KindPolicy {
+    // `Option::is_none_or` would require Rust 1.82.
+    #[allow(clippy::unnecessary_map_or)]
+    fn should_retry(&self, error: &io::Error, retries: u32) -> bool {
+        self.rule(error).is_some_and(|rule| {
+            rule.max_retries.map_or(true, |max| retries < max)
+        })
+    }
+
+    fn backoff(&self, error: &io::Error, retries: u32) -> Duration {
+        self.rule(error)
+            .map_or(Duration::from_secs(0), |rule| rule.backoff.delay(retries))
+    }
+
+    fn retry_reason(&self, error: &io::Error, _: u32) -> RetryReason {
+        match error.kind() {
+            ErrorKind::Interrupted => RetryReason::Interrupted,
+            _ => RetryReason::Kind,
+        }
+    }
+
+    fn retry_limit(&self) -> Option<u32> {
+        self.rules.iter()
+            .try_fold(0, |limit, rule| Some(limit.max(rule.max_retries?)))
+    }
+}
+
+/// Policy retrying errors with the given raw OS error code, without waiting
+/// between attempts.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct OsErrorPolicy(pub i32);
+
+impl RetryPolicy for OsErrorPolicy {
+    fn should_retry(&self, error: &io::Error, _: u32) -> bool {
+        error.raw_os_error() == Some(self.0)
+    }
+
+    fn retry_reason(&self, _: &io::Error, _: u32) -> RetryReason {
+        RetryReason::OsError
+    }
+}
+
+/// Policy never retrying.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct NeverPolicy;
+
+impl RetryPolicy for NeverPolicy {
+    fn should_retry(&self, _: &io::Error, _: u32) -> bool {
+        false
+    }
+
+    fn retry_limit(&self) -> Option<u32> {
+        Some(0)
+    }
+}
+
+/// Policy retrying every error, as many times as needed and without waiting
+/// between attempts.
+///
+/// This should be combined with a bound, e.g. a total budget, unless errors
+/// are known to be transient.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct AlwaysPolicy;
+
+impl RetryPolicy for AlwaysPolicy {
+    fn should_retry(&self, _: &io::Error, _: u32) -> bool {
+        true
+    }
+}
+
+/// Policy retrying errors retried by either of two policies.
+///
+/// See [`RetryPolicy::or`].
+#[derive(Clone, Copy, Debug)]
+pub struct Or<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: RetryPolicy, B: RetryPolicy> RetryPolicy for Or<A, B> {
+    fn should_retry(&self, error: &io::Error, retries: u32) -> bool {
+        self.first.should_retry(error, retries)
+            || self.second.should_retry(error, retries)
+    }
+
+    fn backoff(&self, error: &io::Error, retries: u32) -> Duration {
+        if self.first.should_retry(error, retries) {
+            self.first.backoff(error, retries)
+        } else {
+            self.second.backoff(error, retries)
+        }
+    }
+
+    fn retry_reason(&self, error: &io::Error, retries: u32) -> RetryReason {
+        if self.first.should_retry(error, retries) {
+            self.first.retry_reason(error, retries)
+        } else {
+            self.second.retry_reason(error, retries)
+        }
+    }
+
+    fn retry_limit(&self) -> Option<u32> {
+        Some(self.first.retry_limit()?.max(self.second.retry_limit()?))
+    }
+}
+
+/// Policy retrying errors retried by both of two policies.
+///
+/// See [`RetryPolicy::and`].
+#[derive(Clone, Copy, Debug)]
+pub struct And<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: RetryPolicy, B: RetryPolicy> RetryPolicy for And<A, B> {
+    fn should_retry(&self, error: &io::Error, retries: u32) -> bool {
+        self.first.should_retry(error, retries)
+            && self.second.should_retry(error, retries)
+    }
+
+    fn backoff(&self, error: &io::Error, retries: u32) -> Duration {
+        self.first.backoff(error, retries)
+            .max(self.second.backoff(error, retries))
+    }
+
+    fn retry_reason(&self, error: &io::Error, retries: u32) -> RetryReason {
+        self.first.retry_reason(error, retries)
+    }
+
+    fn retry_limit(&self) -> Option<u32> {
+        match (self.first.retry_limit(), self.second.retry_limit()) {
+            (Some(first), Some(second)) => Some(first.min(second)),
+            (first, second) => first.or(second),
+        }
+    }
+}
+
+/// Policy retrying errors for which a function returns `true`.
+///
+/// See [`from_fn`], also available as `<dyn RetryPolicy>::from_fn`.
+#[derive(Clone, Copy, Debug)]
+pub struct FnPolicy<F> {
+    f: F,
+}
+
+impl<F> RetryPolicy for FnPolicy<F>
+where
+    F: Fn(&io::Error, u32) -> bool,
+{
+    fn should_retry(&self, error: &io::Error, retries: u32) -> bool {
+        (self.f)(error, retries)
+    }
+}
+
+/// Returns a policy retrying an operation when `f` returns `true`.
+///
+/// `f` is called with the error and the number of previous retries of the
+/// failed operation.
+///
+/// ```
+/// use retrio::{policy, RetryBuilder};
+/// use std::io::{Cursor, ErrorKind, Read};
+///
+/// let policy = policy::from_fn(|e, retries| {
+///     e.kind() == ErrorKind::Interrupted && retries < 3
+/// });
+/// let mut reader = RetryBuilder::new()
+///     .policy(policy)
+///     .build(Cursor::new(b"abc".to_vec()));
+/// let mut s = String::new();
+/// reader.read_to_string(&mut s).unwrap();
+/// assert_eq!(s, "abc");
+/// ```
+pub fn from_fn<F>(f: F) -> FnPolicy<F>
+where
+    F: Fn(&io::Error, u32) -> bool,
+{
+    FnPolicy {f}
+}
+
+/// Policy retrying errors wrapping an error of type `E` for which a function
+/// returns `true`.
+///
+/// See [`downcast`].
+pub struct DowncastPolicy<E, F> {
+    f: F,
+    error: PhantomData<fn(&E)>,
+}
+
+impl<E, F: Clone> Clone for DowncastPolicy<E, F> {
+    fn clone(&self) -> Self {
+        DowncastPolicy {f: self.f.clone(), error: PhantomData}
+    }
+}
+
+impl<E, F> fmt::Debug for DowncastPolicy<E, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DowncastPolicy").finish_non_exhaustive()
+    }
+}
+
+impl<E, F> RetryPolicy for DowncastPolicy<E, F>
+where
+    E: Error + 'static,
+    F: Fn(&E) -> bool,
+{
+    fn should_retry(&self, error: &io::Error, _: u32) -> bool {
+        error.get_ref()
+            .and_then(|e| e.downcast_ref::<E>())
+            .is_some_and(&self.f)
+    }
+}
+
+/// Returns a policy retrying errors wrapping an error of type `E`, as returned
+/// by `io::Error::get_ref`, for which `f` returns `true`.
+///
+/// This allows retrying errors that libraries wrap in an `io::Error` of kind
+/// `Other` and that cannot be told apart by kind.
+pub fn downcast<E, F>(f: F) -> DowncastPolicy<E, F>
+where
+    E: Error + 'static,
+    F: Fn(&E) -> bool,
+{
+    DowncastPolicy {f, error: PhantomData}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Backoff, Retry, RetryBuilder};
+    use partial_io::{PartialOp, PartialRead};
+    use std::io::{Cursor, ErrorKind, Read, self};
+    use std::time::Duration;
+    use super::{AlwaysPolicy, DefaultPolicy, FnPolicy, KindPolicy};
+    use super::{NeverPolicy, OsErrorPolicy, RetryPolicy, RetryReason};
+
+    fn require_send<S: Send>() {}
+    fn require_sync<S: Sync>() {}
+
+    #[test]
+    fn wrapper_with_fn_policy_is_send_and_sync() {
+        fn check<F: Fn(&io::Error, u32) -> bool + Send + Sync>(_: &F) {
+            require_send::<Retry<Cursor<Vec<u8>>, FnPolicy<F>>>();
+            require_sync::<Retry<Cursor<Vec<u8>>, FnPolicy<F>>>();
+        }
+        check(&|e: &io::Error, _| e.kind() == ErrorKind::Interrupted);
+        require_send::<Retry<Cursor<Vec<u8>>, DefaultPolicy>>();
+        require_sync::<Retry<Cursor<Vec<u8>>, DefaultPolicy>>();
+    }
+
+    #[test]
+    fn fn_policy_decides_retries() {
+        let policy = || <dyn RetryPolicy>::from_fn(|e, retries| {
+            e.kind() == ErrorKind::TimedOut && retries < 2
+        });
+        let input = &b"Policy test"[..];
+        let ops = |n| vec![PartialOp::Err(ErrorKind::TimedOut); n];
+        let mut out = vec![0u8; input.len()];
+        let mut reader = RetryBuilder::new()
+            .policy(policy())
+            .build(PartialRead::new(input, ops(2)));
+        assert_eq!(reader.read(&mut out).unwrap(), input.len());
+        let mut reader = RetryBuilder::new()
+            .policy(policy())
+            .build(PartialRead::new(input, ops(3)));
+        assert_eq!(reader.read(&mut out).unwrap_err().kind(),
+            ErrorKind::TimedOut);
+        assert!(!policy().should_retry(&ErrorKind::Interrupted.into(), 0));
+    }
+
+    #[test]
+    fn combines_policies() {
+        let policy = KindPolicy::new().on_kind(ErrorKind::TimedOut)
+            .or(OsErrorPolicy(4));
+        let timed_out = io::Error::from(ErrorKind::TimedOut);
+        let os_error = io::Error::from_raw_os_error(4);
+        assert!(policy.should_retry(&timed_out, 0));
+        assert!(policy.should_retry(&os_error, 0));
+        assert!(!policy.should_retry(&ErrorKind::Other.into(), 0));
+        assert_eq!(policy.retry_reason(&timed_out, 0), RetryReason::Kind);
+        assert_eq!(policy.retry_reason(&os_error, 0), RetryReason::OsError);
+        let policy = AlwaysPolicy.and(KindPolicy::new()
+            .on_kind(ErrorKind::TimedOut));
+        assert!(policy.should_retry(&timed_out, 0));
+        assert!(!policy.should_retry(&os_error, 0));
+        assert!(!NeverPolicy.or(NeverPolicy).should_retry(&timed_out, 0));
+    }
+
+    #[test]
+    fn combines_retry_limits() {
+        let limited = |n| DefaultPolicy::new().max_retries(n);
+        assert_eq!(DefaultPolicy::new().retry_limit(), None);
+        assert_eq!(limited(2).or(limited(5)).retry_limit(), Some(5));
+        assert_eq!(limited(2).or(AlwaysPolicy).retry_limit(), None);
+        assert_eq!(limited(2).and(limited(5)).retry_limit(), Some(2));
+        assert_eq!(AlwaysPolicy.and(limited(5)).retry_limit(), Some(5));
+        assert_eq!(NeverPolicy.retry_limit(), Some(0));
+        let policy = KindPolicy::new()
+            .on_kind(ErrorKind::Interrupted).max(4)
+            .on_kind(ErrorKind::TimedOut).max(1);
+        assert_eq!(policy.retry_limit(), Some(4));
+        let policy = policy.on_kind(ErrorKind::WouldBlock);
+        assert_eq!(policy.retry_limit(), None);
+    }
+
+    #[test]
+    fn kind_policy_applies_rule_of_each_kind() {
+        let policy = KindPolicy::new()
+            .on_kind(ErrorKind::Interrupted)
+            .on_kind(ErrorKind::WouldBlock)
+            .max(5)
+            .backoff(Backoff::Constant(Duration::from_millis(1)))
+            .on_kind(ErrorKind::TimedOut)
+            .max(2);
+        let input = &b"Kind policy"[..];
+        let read = |kind, n| {
+            let ops = vec![PartialOp::Err(kind); n];
+            let mut reader = RetryBuilder::new()
+                .policy(policy.clone())
+                .build(PartialRead::new(input, ops));
+            reader.read(&mut [0u8; 16]).map_err(|e| e.kind())
+        };
+        assert_eq!(read(ErrorKind::Interrupted, 20), Ok(input.len()));
+        assert_eq!(read(ErrorKind::WouldBlock, 5), Ok(input.len()));
+        assert_eq!(read(ErrorKind::WouldBlock, 6), Err(ErrorKind::WouldBlock));
+        assert_eq!(read(ErrorKind::TimedOut, 2), Ok(input.len()));
+        assert_eq!(read(ErrorKind::TimedOut, 3), Err(ErrorKind::TimedOut));
+        assert_eq!(read(ErrorKind::Other, 1), Err(ErrorKind::Other));
+        let would_block = io::Error::from(ErrorKind::WouldBlock);
+        assert_eq!(RetryPolicy::backoff(&policy, &would_block, 0),
+            Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright (C) 2018 Stephane Raux. Distributed under the MIT license.
+
+use crate::Retry;
+use std::io::{Read, self, Write};
+
+/// Call made to a [`Recording`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Operation {
+    /// `read` into a buffer of the given length.
+    Read(usize),
+    /// `write` of the given bytes.
+    Write(Vec<u8>),
+    /// `flush`.
+    Flush,
+}
+
+/// Reader or writer recording every call made to it before forwarding it to
+/// the inner value.
+///
+/// Calls are recorded whether they succeed or not, so that wrapping a
+/// `Recording` in a [`Retry`] shows each attempt, including the ones that
+/// were retried. Wrapping `io::empty()` or `io::sink()` records the calls
+/// without performing any actual IO.
+#[derive(Clone, Debug, Default)]
+pub struct Recording<T> {
+    inner: T,
+    operations: Vec<Operation>,
+}
+
+impl<T> Recording<T> {
+    /// Records the calls made to `inner`.
+    pub fn new(inner: T) -> Self {
+        Recording {inner, operations: Vec::new()}
+    }
+
+    /// Returns the calls made so far, oldest first.
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Returns the inner value.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> Retry<Recording<T>> {
+    /// Wraps `inner`, recording every attempt made to read from or write to
+    /// it.
+    ///
+    /// See [`Recording`].
+    pub fn recording(inner: T) -> Self {
+        Retry::new(Recording::new(inner))
+    }
+}
+
+impl<T, P> Retry<Recording<T>, P> {
+    /// Returns the calls made to the inner value so far, oldest first.
+    pub fn operations(&self) -> &[Operation] {
+        self.inner.operations()
+    }
+}
+
+impl<T: Read> Read for Recording<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.operations.push(Operation::Read(buf.len()));
+        self.inner.read(buf)
+    }
+}
+
+impl<T: Write> Write for Recording<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.operations.push(Operation::Write(buf.to_vec()));
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.operations.push(Operation::Flush);
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Retry, RetryBuilder};
+    use partial_io::{PartialOp, PartialRead, PartialWrite};
+    use std::io::{ErrorKind, Read, Write};
+    use super::{Operation, Recording};
+
+    #[test]
+    fn records_retried_attempts() {
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(3),
+            PartialOp::Err(ErrorKind::WouldBlock),
+        ];
+        let mut writer = RetryBuilder::new()
+            .retry_on(ErrorKind::WouldBlock)
+            .build(Recording::new(PartialWrite::new(Vec::new(), ops)));
+        writer.write_all(b"hello").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.operations(), [
+            Operation::Write(b"hello".to_vec()),
+            Operation::Write(b"hello".to_vec()),
+            Operation::Write(b"lo".to_vec()),
+            Operation::Write(b"lo".to_vec()),
+            Operation::Flush,
+        ]);
+        let ops = vec![PartialOp::Err(ErrorKind::Interrupted)];
+        let mut reader = Retry::recording(PartialRead::new(&b"abc"[..], ops));
+        let mut out = [0u8; 4];
+        assert_eq!(reader.read(&mut out).unwrap(), 3);
+        assert_eq!(reader.read(&mut out[3..]).unwrap(), 0);
+        assert_eq!(reader.operations(), [
+            Operation::Read(4),
+            Operation::Read(4),
+            Operation::Read(1),
+        ]);
+    }
+}
@@ -0,0 +1,94 @@
+// Copyright (C) 2018 Stephane Raux. Distributed under the MIT license.
+
+use crate::Retry;
+use crate::policy::DefaultPolicy;
+use std::cell::RefCell;
+use std::io::{Read, Seek, SeekFrom, self, Write};
+use std::rc::Rc;
+
+/// Wrapper retrying operations on a value shared through `Rc<RefCell<T>>`.
+///
+/// The value is borrowed for each attempt of an operation and released before
+/// waiting between attempts, so that other handles can use it in between.
+pub type SharedRetry<T, P = DefaultPolicy> = Retry<Shared<T>, P>;
+
+/// Handle to a value shared through `Rc<RefCell<T>>`, borrowed for each call.
+///
+/// Each call to `read`, `write`, `flush` or `seek` mutably borrows the value
+/// for the duration of the call and panics if it is already borrowed, like
+/// `RefCell::borrow_mut`.
+#[derive(Debug, Default)]
+pub struct Shared<T>(pub Rc<RefCell<T>>);
+
+impl<T> Shared<T> {
+    /// Wraps a shared value.
+    pub fn new(value: Rc<RefCell<T>>) -> Self {
+        Shared(value)
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Shared(self.0.clone())
+    }
+}
+
+impl<T> Retry<Shared<T>> {
+    /// Wraps a value shared through `Rc<RefCell<T>>`.
+    ///
+    /// See [`SharedRetry`].
+    pub fn shared(value: Rc<RefCell<T>>) -> Self {
+        Retry::new(Shared(value))
+    }
+}
+
+impl<T: Read> Read for Shared<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+impl<T: Write> Write for Shared<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+impl<T: Seek> Seek for Shared<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.borrow_mut().seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Retry, RetryBuilder};
+    use partial_io::{PartialOp, PartialWrite};
+    use std::cell::RefCell;
+    use std::io::{ErrorKind, Write};
+    use std::rc::Rc;
+    use super::{Shared, SharedRetry};
+
+    #[test]
+    fn writes_through_shared_handles() {
+        let ops = vec![
+            PartialOp::Err(ErrorKind::Interrupted),
+            PartialOp::Limited(2),
+            PartialOp::Err(ErrorKind::Interrupted),
+        ];
+        let sink = Rc::new(RefCell::new(PartialWrite::new(Vec::new(), ops)));
+        let mut first = Retry::shared(sink.clone());
+        let mut second: SharedRetry<_> = RetryBuilder::new()
+            .build(Shared::new(sink.clone()));
+        first.write_all(b"one ").unwrap();
+        second.write_all(b"two ").unwrap();
+        first.write_all(b"three").unwrap();
+        drop((first, second));
+        let sink = Rc::try_unwrap(sink).ok().unwrap().into_inner();
+        assert_eq!(sink.into_inner(), b"one two three");
+    }
+}
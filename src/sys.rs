@@ -0,0 +1,74 @@
+// Copyright (C) 2018 Stephane Raux. Distributed under the MIT license.
+
+use crate::Interest;
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::Duration;
+
+/// Blocks until `inner` is ready for the given kind of operation, or fails
+/// with `TimedOut` once `timeout` has elapsed.
+pub(crate) fn wait<T: AsRawFd>(inner: &T, interest: Interest,
+    timeout: Option<Duration>) -> io::Result<()>
+{
+    wait_fd(inner.as_raw_fd(), interest, timeout)
+}
+
+fn wait_fd(fd: RawFd, interest: Interest, timeout: Option<Duration>)
+    -> io::Result<()>
+{
+    let events = match interest {
+        Interest::Read => libc::POLLIN,
+        Interest::Write => libc::POLLOUT,
+    };
+    // Rounded up so as not to wake up before the timeout has elapsed.
+    let timeout = timeout.map_or(-1, |timeout| {
+        let millis = timeout.as_millis()
+            + u128::from(timeout.subsec_nanos() % 1_000_000 != 0);
+        millis.min(libc::c_int::MAX as u128) as libc::c_int
+    });
+    let mut poll_fd = libc::pollfd {fd, events, revents: 0};
+    // Safety: `poll_fd` is a valid `pollfd` for the whole call and its count
+    // is 1.
+    match unsafe { libc::poll(&mut poll_fd, 1, timeout) } {
+        0 => return Err(io::Error::new(io::ErrorKind::TimedOut,
+            "timed out waiting for readiness")),
+        n if n > 0 => return Ok(()),
+        _ => {}
+    }
+    match io::Error::last_os_error() {
+        // The operation is retried anyway, which polls again if needed.
+        ref e if e.kind() == io::ErrorKind::Interrupted => Ok(()),
+        e => Err(e),
+    }
+}
+
+/// Returns the receive and send timeouts of a socket.
+pub(crate) fn socket_timeouts<T: AsRawFd>(socket: &T)
+    -> io::Result<(Option<Duration>, Option<Duration>)>
+{
+    let fd = socket.as_raw_fd();
+    Ok((socket_timeout(fd, libc::SO_RCVTIMEO)?,
+        socket_timeout(fd, libc::SO_SNDTIMEO)?))
+}
+
+fn socket_timeout(fd: RawFd, option: libc::c_int)
+    -> io::Result<Option<Duration>>
+{
+    let mut timeout = libc::timeval {tv_sec: 0, tv_usec: 0};
+    let mut len = mem::size_of::<libc::timeval>() as libc::socklen_t;
+    // Safety: `timeout` and `len` are valid for writes for the whole call and
+    // `len` is the size of `timeout`.
+    let res = unsafe {
+        libc::getsockopt(fd, libc::SOL_SOCKET, option,
+            &mut timeout as *mut libc::timeval as *mut libc::c_void, &mut len)
+    };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if timeout.tv_sec == 0 && timeout.tv_usec == 0 {
+        return Ok(None);
+    }
+    Ok(Some(Duration::new(timeout.tv_sec as u64,
+        timeout.tv_usec as u32 * 1000)))
+}